@@ -0,0 +1,5 @@
+// Prints the wire format description generated from the codec constants.
+// `cargo run --bin t3p0-wire-docs > WIRE_FORMAT.md`
+fn main() {
+    print!("{}", t3p0::wire_format::markdown());
+}
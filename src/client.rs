@@ -0,0 +1,944 @@
+// The client side of the protocol.
+// Frontends get typed events describing what changed in the game instead of raw frames.
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    game_state::{result_of, GameResult},
+    match_options::{MatchOptions, MatchOptionsTrait},
+    nack::{Nack, NackTrait},
+    player::{Player, PlayerTrait},
+    request::{DataRequest, Request},
+};
+
+/// How long one connection attempt gets before the next address is tried alongside it (RFC 8305).
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientEvent {
+    /// The opponent placed a mark on this square, 0 is the top left and 8 the bottom right.
+    OpponentMoved(u8),
+    /// It's now this client's turn.
+    YourTurn,
+    /// The game finished. No more moves can be made.
+    GameOver(GameResult),
+}
+
+/// Works out what happened between two states received from the server.
+///
+/// # Arguments
+///
+/// * `previous` - The state the client had, `None` before the first state arrives.
+/// * `current` - The state just received.
+/// * `is_player_two` - Whether this client is the second player.
+///
+/// # Returns
+///
+/// * `Vec<ClientEvent>` - The events in the order a frontend should show them. A finished game
+///   ends with `GameOver` instead of `YourTurn`.
+pub fn events(
+    previous: Option<Request>,
+    current: Request,
+    is_player_two: bool,
+) -> Vec<ClientEvent> {
+    let mut events = Vec::new();
+    if current.is_ok_response() {
+        return events;
+    }
+    let my_turn = current.get_is_p2_turn() == is_player_two;
+    let was_my_turn = previous
+        .filter(|previous| !previous.is_ok_response())
+        .map(|previous| previous.get_is_p2_turn() == is_player_two);
+
+    // Squares that filled in while it was the opponent's turn are the opponent's move.
+    if let Some(previous) = previous.filter(|_| was_my_turn == Some(false) && my_turn) {
+        let placed = current.get_board_state() & !previous.get_board_state();
+        for square in 0..9 {
+            if placed & (1 << square) != 0 {
+                events.push(ClientEvent::OpponentMoved(square));
+            }
+        }
+    }
+    let finished = previous.is_some_and(|previous| result(previous) != GameResult::InProgress);
+    match result(current) {
+        _ if finished => {}
+        GameResult::InProgress => {
+            if my_turn && was_my_turn != Some(true) {
+                events.push(ClientEvent::YourTurn);
+            }
+        }
+        over => events.push(ClientEvent::GameOver(over)),
+    }
+    events
+}
+
+/// How the game in a state stands, see `result_of`.
+fn result(state: Request) -> GameResult {
+    let o_marks = state.get_o_marks();
+    result_of(state.get_board_state() & !o_marks, o_marks)
+}
+
+/// What a bot is shown when it's asked for a move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardView {
+    /// Squares with a mark on them, bit 0 is the top left and bit 8 the bottom right.
+    pub occupied: u16,
+    /// The occupied squares holding the second player's mark, O.
+    pub o_marks: u16,
+    /// How many marks have been placed.
+    pub turn: u8,
+    pub is_player_two: bool,
+}
+
+impl BoardView {
+    /// Returns true if `square` is on the board and has no mark on it.
+    pub fn is_free(&self, square: u8) -> bool {
+        square < 9 && self.occupied & (1 << square) == 0
+    }
+
+    /// The squares holding this client's marks.
+    pub fn own_marks(&self) -> u16 {
+        if self.is_player_two {
+            self.o_marks
+        } else {
+            self.occupied & !self.o_marks
+        }
+    }
+
+    /// The squares holding the opponent's marks.
+    pub fn opponent_marks(&self) -> u16 {
+        self.occupied & !self.own_marks()
+    }
+
+    /// The squares a move can be made on, in order.
+    pub fn free_squares(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..9).filter(|square| self.is_free(*square))
+    }
+}
+
+/// A bot that only decides moves. `run_bot` handles the connection, handshake and framing.
+/// Any `FnMut(&BoardView) -> u8` is a bot, so a plain function can be plugged in.
+pub trait UserBot {
+    /// Picks the square to play, 0 is the top left and 8 the bottom right.
+    fn choose_move(&mut self, board: &BoardView) -> u8;
+}
+
+impl<F: FnMut(&BoardView) -> u8> UserBot for F {
+    fn choose_move(&mut self, board: &BoardView) -> u8 {
+        self(board)
+    }
+}
+
+fn invalid_data(reason: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Reads the next frame from the server, a data frame or the authoritative state of a NACK
+/// along with the NACK. Returns `None` when the server has closed the connection.
+fn read_state(stream: &mut TcpStream) -> io::Result<Option<(Request, Option<Nack>)>> {
+    let mut bytes = [0u8; 8];
+    match stream.read_exact(&mut bytes[..4]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let header = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if !Nack::is_nack_header(header) {
+        return Ok(Some((Request(header), None)));
+    }
+    stream.read_exact(&mut bytes[4..])?;
+    let nack = Nack::from_bytes(&bytes).map_err(invalid_data)?;
+    Ok(Some((nack.state, Some(nack))))
+}
+
+/// Does the client side of the handshake, optionally resuming as a player from an earlier
+/// connection, e.g. one kept in a `ClientSession`.
+///
+/// # Arguments
+///
+/// * `stream` - A new connection to the server.
+/// * `resume` - The player to continue as, or `None` to keep the id the server assigns.
+/// * `options` - What kind of match to join. Ignored when resuming, since the player goes back
+///   to their game.
+///
+/// # Returns
+///
+/// * `Player` - Who this connection plays as. Save it to resume after a restart.
+///
+/// # Errors
+///
+/// * `io::Error` - If the connection fails, or `InvalidData` if the server doesn't accept the resumed id.
+pub fn handshake(
+    stream: &mut TcpStream,
+    resume: Option<&Player>,
+    options: MatchOptions,
+) -> io::Result<Player> {
+    let ok = Request::new_data_request(true).0.to_be_bytes();
+    stream.write_all(&ok)?;
+    // The server sends the assigned id in little endian field order.
+    let mut assigned = [0u8; 16];
+    stream.read_exact(&mut assigned)?;
+    let Some(player) = resume else {
+        stream.write_all(&options.to_request().0.to_be_bytes())?;
+        return Ok(Player::from_bytes(Uuid::from_bytes_le(assigned).as_bytes()));
+    };
+    stream.write_all(player.get_id().as_bytes())?;
+    let mut answer = [0u8; 4];
+    stream.read_exact(&mut answer)?;
+    if !Request(u32::from_be_bytes(answer)).is_ok_response() {
+        return Err(invalid_data("The server didn't accept the resumed player."));
+    }
+    Ok(player.clone())
+}
+
+/// Plays a game with `bot` over a connection to the server until the game is finished or the
+/// server closes the connection. The bot waits for the state the server sends when the match
+/// starts, since the server refuses moves from a player without a game, and takes its side from
+/// that state's second side bit. A move NACKed as
+/// retryable is sent again after the NACK's backoff hint. Any other NACKed move is dropped and
+/// the bot is asked again from the server's state. A move that ends the game is only done once
+/// the server has answered it.
+///
+/// # Arguments
+///
+/// * `stream` - A connection that hasn't done the handshake yet, e.g. from `connect`.
+/// * `bot` - Chooses a square whenever it's this client's turn.
+///
+/// # Errors
+///
+/// * `io::Error` - If the connection fails, `InvalidInput` if the bot picks a square that isn't
+///   free, or `Other` if the server refuses the move that ends the game.
+pub fn run_bot<B: UserBot>(mut stream: TcpStream, bot: &mut B) -> io::Result<()> {
+    handshake(&mut stream, None, MatchOptions::default())?;
+
+    let start = loop {
+        match read_state(&mut stream)? {
+            None => return Ok(()),
+            Some((state, _)) if state.is_ok_response() => continue,
+            Some((state, _)) => break state,
+        }
+    };
+    let is_player_two = start.is_second_side();
+    let mut current = start.set_second_side(false);
+    let mut previous = None;
+    // The last move sent, until the server answers it.
+    let mut unanswered = None;
+    loop {
+        if unanswered.is_none() && result(current) != GameResult::InProgress {
+            return Ok(());
+        }
+        if events(previous, current, is_player_two).contains(&ClientEvent::YourTurn) {
+            let board = BoardView {
+                occupied: current.get_board_state(),
+                o_marks: current.get_o_marks(),
+                turn: current.get_turn(),
+                is_player_two,
+            };
+            let square = bot.choose_move(&board);
+            if !board.is_free(square) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The bot chose a square that isn't free.",
+                ));
+            }
+            let next = current
+                .increment_turn_and_message()
+                .map_err(invalid_data)?
+                .set_board(board.occupied | 1 << square);
+            let next = if is_player_two {
+                next.set_o_marks(board.o_marks | 1 << square)
+            } else {
+                next
+            };
+            stream.write_all(&next.0.to_be_bytes())?;
+            previous = Some(next);
+            unanswered = Some(next);
+            current = next;
+        }
+
+        let Some((state, nack)) = read_state(&mut stream)? else {
+            return Ok(());
+        };
+        if state.is_ok_response() {
+            continue;
+        }
+        if let (Some(backoff), Some(sent)) = (nack.and_then(|nack| nack.retry_after()), unanswered)
+        {
+            thread::sleep(backoff);
+            stream.write_all(&sent.0.to_be_bytes())?;
+            continue;
+        }
+        // Otherwise the bot would leave while the server still waits for the game's last move.
+        if nack.is_some() && unanswered.is_some_and(|sent| result(sent) != GameResult::InProgress) {
+            return Err(io::Error::other(
+                "The server refused the move that ends the game.",
+            ));
+        }
+        unanswered = None;
+        // After a NACK the bot's move never happened, so the turn is offered again.
+        previous = nack.is_none().then_some(current);
+        current = state;
+    }
+}
+
+/// Orders addresses IPv6 first and then alternating between families, keeping the resolver's
+/// order within each family, so a broken family only delays the connection by one attempt.
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (mut v6, mut v4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(SocketAddr::is_ipv6);
+    v6.reverse();
+    v4.reverse();
+    let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+    while !v6.is_empty() || !v4.is_empty() {
+        ordered.extend(v6.pop());
+        ordered.extend(v4.pop());
+    }
+    ordered
+}
+
+/// Connects to the first server that answers, happy eyeballs style.
+/// Attempts start `CONNECTION_ATTEMPT_DELAY` apart, or straight away when the previous one fails,
+/// and the first to succeed wins. Attempts that finish later are dropped.
+///
+/// # Arguments
+///
+/// * `addresses` - Candidate addresses, or a host name that resolves to several.
+/// * `timeout` - How long to keep trying in total.
+///
+/// # Errors
+///
+/// * `io::Error` - The last attempt's error if every address failed, or `TimedOut`.
+pub fn connect<A: ToSocketAddrs>(addresses: A, timeout: Duration) -> io::Result<TcpStream> {
+    let candidates = interleave_families(addresses.to_socket_addrs()?.collect());
+    if candidates.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No addresses to connect to.",
+        ));
+    }
+    let deadline = Instant::now() + timeout;
+    let (tx, rx) = mpsc::channel();
+    let mut started = 0;
+    let mut failed = 0;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Timed out connecting to the server.",
+            ));
+        }
+        if started < candidates.len() {
+            let address = candidates[started];
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let _ = tx.send(TcpStream::connect_timeout(&address, remaining));
+            });
+            started += 1;
+        }
+
+        let wait = if started < candidates.len() {
+            CONNECTION_ATTEMPT_DELAY.min(remaining)
+        } else {
+            remaining
+        };
+        match rx.recv_timeout(wait) {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                failed += 1;
+                if failed == candidates.len() {
+                    return Err(e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => unreachable!("the sender is held above"),
+        }
+    }
+}
+
+/// An async connection to a server for bots and integration tests. It keeps the last state the
+/// server sent, so moves are given as a square and the framing is done here.
+///
+/// A new player gets the empty board once they are matched, from `next_state`, and
+/// `is_player_two` then says which side they are on.
+#[cfg(feature = "async-client")]
+#[derive(Debug)]
+pub struct Client {
+    stream: tokio::net::TcpStream,
+    state: Request,
+    is_player_two: Option<bool>,
+}
+
+#[cfg(feature = "async-client")]
+impl Client {
+    /// Opens a connection to the server. Call `handshake` next.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If none of the addresses accept the connection.
+    pub async fn connect<A: tokio::net::ToSocketAddrs>(address: A) -> io::Result<Client> {
+        Ok(Client {
+            stream: tokio::net::TcpStream::connect(address).await?,
+            state: Request::new_data_request(false),
+            is_player_two: None,
+        })
+    }
+
+    /// Does the client side of the handshake, see the blocking `handshake`.
+    ///
+    /// # Arguments
+    ///
+    /// * `resume` - The player to continue as, or `None` to keep the id the server assigns.
+    /// * `options` - What kind of match to join. Ignored when resuming.
+    ///
+    /// # Returns
+    ///
+    /// * `Player` - Who this connection plays as.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If the connection fails, or `InvalidData` if the server doesn't accept the resumed id.
+    pub async fn handshake(
+        &mut self,
+        resume: Option<&Player>,
+        options: MatchOptions,
+    ) -> io::Result<Player> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let ok = Request::new_data_request(true).0.to_be_bytes();
+        self.stream.write_all(&ok).await?;
+        // The server sends the assigned id in little endian field order.
+        let mut assigned = [0u8; 16];
+        self.stream.read_exact(&mut assigned).await?;
+        let Some(player) = resume else {
+            self.stream
+                .write_all(&options.to_request().0.to_be_bytes())
+                .await?;
+            return Ok(Player::from_bytes(Uuid::from_bytes_le(assigned).as_bytes()));
+        };
+        self.stream.write_all(player.get_id().as_bytes()).await?;
+        if !self.read_frame().await?.0.is_ok_response() {
+            return Err(invalid_data("The server didn't accept the resumed player."));
+        }
+        Ok(player.clone())
+    }
+
+    /// The last state the server sent.
+    pub fn state(&self) -> Request {
+        self.state
+    }
+
+    /// Whether this client places O and moves second.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<bool>` - `None` until the match start state has been received. A resumed player
+    ///   doesn't get one.
+    pub fn is_player_two(&self) -> Option<bool> {
+        self.is_player_two
+    }
+
+    /// How the game stands in the last state the server sent. Once it isn't `InProgress` the
+    /// server refuses any more moves.
+    pub fn result(&self) -> GameResult {
+        result(self.state)
+    }
+
+    /// Places a mark and waits for the server to accept it. The mark is X or O depending on
+    /// whose move it is in the current state.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The square, 0 is the top left and 8 the bottom right.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - `InvalidInput` if the square isn't free or the server refuses the move,
+    ///   in which case `state` holds the server's state. Otherwise the connection failed.
+    pub async fn submit_move(&mut self, cell: u8) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let occupied = self.state.get_board_state();
+        if cell >= 9 || occupied & 1 << cell != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The square isn't free.",
+            ));
+        }
+        let next = self
+            .state
+            .increment_turn_and_message()
+            .map_err(invalid_data)?
+            .set_board(occupied | 1 << cell);
+        // The first player makes the odd numbered moves.
+        let next = if next.get_message_number() % 2 == 0 {
+            next.set_o_marks(self.state.get_o_marks() | 1 << cell)
+        } else {
+            next
+        };
+        loop {
+            self.stream.write_all(&next.0.to_be_bytes()).await?;
+            let (reply, nack) = self.read_frame().await?;
+            if reply.is_ok_response() {
+                continue;
+            }
+            if let Some(backoff) = nack.and_then(|nack| nack.retry_after()) {
+                tokio::time::sleep(backoff).await;
+                continue;
+            }
+            self.state = reply;
+            if reply == next {
+                return Ok(());
+            }
+            let reason = nack.map_or("The server didn't accept the move.", |nack| {
+                nack.code.description()
+            });
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+        }
+    }
+
+    /// Waits for the next state from the server, skipping pings.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - `UnexpectedEof` if the server closed the connection.
+    pub async fn next_state(&mut self) -> io::Result<Request> {
+        loop {
+            let (state, _) = self.read_frame().await?;
+            if state.is_ok_response() {
+                continue;
+            }
+            // Only the match start is at message 0, and it carries the side.
+            if state.get_message_number() == 0 && state.get_board_state() == 0 {
+                self.is_player_two = Some(state.is_second_side());
+            }
+            let state = state.set_second_side(false);
+            self.state = state;
+            return Ok(state);
+        }
+    }
+
+    /// Waits until the opponent places a mark.
+    ///
+    /// # Returns
+    ///
+    /// * `u8` - The square the opponent played.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - `UnexpectedEof` if the server closed the connection.
+    pub async fn await_opponent_move(&mut self) -> io::Result<u8> {
+        loop {
+            let before = self.state.get_board_state();
+            let placed = self.next_state().await?.get_board_state() & !before;
+            if placed != 0 {
+                return Ok(placed.trailing_zeros() as u8);
+            }
+        }
+    }
+
+    /// Reads a data frame, or the authoritative state of a NACK along with the NACK.
+    async fn read_frame(&mut self) -> io::Result<(Request, Option<Nack>)> {
+        use tokio::io::AsyncReadExt;
+
+        let mut bytes = [0u8; 8];
+        self.stream.read_exact(&mut bytes[..4]).await?;
+        let header = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if !Nack::is_nack_header(header) {
+            return Ok((Request(header), None));
+        }
+        self.stream.read_exact(&mut bytes[4..]).await?;
+        let nack = Nack::from_bytes(&bytes).map_err(invalid_data)?;
+        Ok((nack.state, Some(nack)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn state(turn: u8, board: u16) -> Request {
+        Request::new_data_request(false)
+            .set_turn(turn)
+            .set_message_number(turn)
+            .set_p2_turn(turn % 2 == 1)
+            .set_board(board)
+    }
+
+    #[test]
+    fn first_state_on_my_turn() {
+        assert_eq!(
+            events(None, state(0, 0), false),
+            vec![ClientEvent::YourTurn]
+        );
+        assert!(events(None, state(0, 0), true).is_empty());
+    }
+
+    #[test]
+    fn opponent_move_then_my_turn() {
+        assert_eq!(
+            events(Some(state(0, 0)), state(1, 0b10000), true),
+            vec![ClientEvent::OpponentMoved(4), ClientEvent::YourTurn]
+        );
+    }
+
+    #[test]
+    fn my_own_move_is_not_reported() {
+        assert!(events(Some(state(0, 0)), state(1, 0b10000), false).is_empty());
+    }
+
+    #[test]
+    fn repeated_state_has_no_events() {
+        assert!(events(Some(state(1, 0b10000)), state(1, 0b10000), true).is_empty());
+    }
+
+    #[test]
+    fn winning_move_ends_the_game() {
+        // X X . / O O . / . . X, then O completes the middle row.
+        let before = state(5, 0b100_011_011).set_o_marks(0b11_000);
+        let after = state(6, 0b100_111_011).set_o_marks(0b111_000);
+        assert_eq!(
+            events(Some(before), after, false),
+            vec![
+                ClientEvent::OpponentMoved(5),
+                ClientEvent::GameOver(GameResult::OWins)
+            ]
+        );
+        assert!(events(Some(after), after, false).is_empty());
+    }
+
+    #[test]
+    fn pings_have_no_events() {
+        let ok = Request::new_data_request(true);
+        assert!(events(Some(state(0, 0)), ok, false).is_empty());
+        // A ping in between doesn't hide the turn change.
+        assert_eq!(
+            events(Some(ok), state(2, 0b11), false),
+            vec![ClientEvent::YourTurn]
+        );
+    }
+
+    #[test]
+    fn board_view_free_squares() {
+        let board = BoardView {
+            occupied: 0b1_0001_0001,
+            o_marks: 0b0_0001_0000,
+            turn: 3,
+            is_player_two: true,
+        };
+        assert_eq!(board.own_marks(), 0b0_0001_0000);
+        assert_eq!(board.opponent_marks(), 0b1_0000_0001);
+        assert!(!board.is_free(0));
+        assert!(board.is_free(1));
+        assert!(!board.is_free(9));
+        assert_eq!(board.free_squares().collect::<Vec<_>>(), [1, 2, 3, 5, 6, 7]);
+    }
+
+    /// Accepts one client, does the server side of the handshake and returns the connection.
+    fn fake_server() -> (SocketAddr, thread::JoinHandle<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut frame = [0u8; 4];
+            socket.read_exact(&mut frame).unwrap();
+            socket.write_all(&[7; 16]).unwrap();
+            socket.read_exact(&mut frame).unwrap();
+            socket
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn handshake_keeps_assigned_id() {
+        let (address, server) = fake_server();
+        let mut stream = TcpStream::connect(address).unwrap();
+        let player = handshake(&mut stream, None, MatchOptions::default()).unwrap();
+        server.join().unwrap();
+        assert_eq!(*player.get_id(), Uuid::from_bytes_le([7; 16]));
+    }
+
+    #[test]
+    fn handshake_resumes_player() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            read_frame(&mut socket);
+            socket.write_all(&[7; 16]).unwrap();
+            let mut resumed = [0u8; 16];
+            socket.read_exact(&mut resumed).unwrap();
+            socket
+                .write_all(&Request::new_data_request(true).0.to_be_bytes())
+                .unwrap();
+            resumed
+        });
+        let previous = Player::from_bytes(&[9; 16]);
+        let mut stream = TcpStream::connect(address).unwrap();
+        assert_eq!(
+            handshake(&mut stream, Some(&previous), MatchOptions::default()).unwrap(),
+            previous
+        );
+        assert_eq!(server.join().unwrap(), [9; 16]);
+    }
+
+    fn read_frame(socket: &mut TcpStream) -> Request {
+        let mut frame = [0u8; 4];
+        socket.read_exact(&mut frame).unwrap();
+        Request(u32::from_be_bytes(frame))
+    }
+
+    #[test]
+    fn bot_plays_first_free_square() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut first_free = |board: &BoardView| board.free_squares().next().unwrap();
+            run_bot(stream, &mut first_free)
+        });
+        let mut socket = server.join().unwrap();
+        socket.write_all(&state(0, 0).0.to_be_bytes()).unwrap();
+
+        let first = read_frame(&mut socket);
+        assert_eq!(first, state(1, 0b1));
+        assert!(first.validate_request().is_ok());
+        socket.write_all(&state(2, 0b11).0.to_be_bytes()).unwrap();
+        assert_eq!(read_frame(&mut socket), state(3, 0b111));
+        drop(socket);
+        assert!(bot.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn retryable_nack_resends_the_move() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut moves = [4].into_iter();
+            let mut scripted = |_: &BoardView| moves.next().unwrap();
+            run_bot(stream, &mut scripted)
+        });
+        let mut socket = server.join().unwrap();
+        socket.write_all(&state(0, 0).0.to_be_bytes()).unwrap();
+
+        assert_eq!(read_frame(&mut socket), state(1, 0b10000));
+        let nack = Nack::new(crate::nack::NackCode::RateLimited, state(0, 0));
+        let nacked = Instant::now();
+        socket.write_all(&nack.to_bytes()).unwrap();
+        assert_eq!(read_frame(&mut socket), state(1, 0b10000));
+        assert!(nacked.elapsed() >= nack.backoff);
+        drop(socket);
+        assert!(bot.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn nacked_move_is_chosen_again() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut moves = [4, 0].into_iter();
+            let mut scripted = |_: &BoardView| moves.next().unwrap();
+            run_bot(stream, &mut scripted)
+        });
+        let mut socket = server.join().unwrap();
+        socket.write_all(&state(0, 0).0.to_be_bytes()).unwrap();
+
+        assert_eq!(read_frame(&mut socket), state(1, 0b10000));
+        let nack = Nack::new(crate::nack::NackCode::IllegalMove, state(0, 0));
+        socket.write_all(&nack.to_bytes()).unwrap();
+        assert_eq!(read_frame(&mut socket), state(1, 0b1));
+        drop(socket);
+        assert!(bot.join().unwrap().is_ok());
+    }
+
+    /// The empty board the server sends when a match starts.
+    fn match_start(is_second_side: bool) -> Request {
+        state(0, 0).set_second_side(is_second_side)
+    }
+
+    #[test]
+    fn player_two_bot_places_o_marks() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut first_free = |board: &BoardView| board.free_squares().next().unwrap();
+            run_bot(stream, &mut first_free)
+        });
+        let mut socket = server.join().unwrap();
+        socket
+            .write_all(&match_start(true).0.to_be_bytes())
+            .unwrap();
+        socket.write_all(&state(1, 0b1).0.to_be_bytes()).unwrap();
+        let reply = read_frame(&mut socket);
+        assert_eq!(reply, state(2, 0b11).set_o_marks(0b10));
+        assert!(reply.validate_request().is_ok());
+        drop(socket);
+        assert!(bot.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn bot_stops_when_the_game_is_won() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            run_bot(stream, &mut |_: &BoardView| 8)
+        });
+        let mut socket = server.join().unwrap();
+        socket
+            .write_all(&match_start(true).0.to_be_bytes())
+            .unwrap();
+        let won = state(5, 0b11_111).set_o_marks(0b11_000);
+        socket.write_all(&won.0.to_be_bytes()).unwrap();
+        // The connection is still open, so the bot only returns because the game is over.
+        assert!(bot.join().unwrap().is_ok());
+    }
+
+    /// X on the top left and top middle, O on the two squares below them, X to move.
+    fn before_win() -> Request {
+        state(4, 0b11_011).set_o_marks(0b11_000)
+    }
+
+    #[test]
+    fn bot_waits_for_its_winning_move_to_be_answered() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            run_bot(stream, &mut |_: &BoardView| 2)
+        });
+        let mut socket = server.join().unwrap();
+        socket.write_all(&before_win().0.to_be_bytes()).unwrap();
+        let win = read_frame(&mut socket);
+        assert_eq!(win, state(5, 0b11_111).set_o_marks(0b11_000));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!bot.is_finished());
+        socket.write_all(&win.0.to_be_bytes()).unwrap();
+        assert!(bot.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn nacked_winning_move_is_an_error() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            run_bot(stream, &mut |_: &BoardView| 2)
+        });
+        let mut socket = server.join().unwrap();
+        socket.write_all(&before_win().0.to_be_bytes()).unwrap();
+        read_frame(&mut socket);
+        let nack = Nack::new(crate::nack::NackCode::IllegalMove, before_win());
+        socket.write_all(&nack.to_bytes()).unwrap();
+        let error = bot.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn bot_choosing_a_taken_square_is_an_error() {
+        let (address, server) = fake_server();
+        let bot = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            run_bot(stream, &mut |_: &BoardView| 0)
+        });
+        let mut socket = server.join().unwrap();
+        socket
+            .write_all(&match_start(true).0.to_be_bytes())
+            .unwrap();
+        socket.write_all(&state(1, 0b1).0.to_be_bytes()).unwrap();
+        let error = bot.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    fn closed_port() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[test]
+    fn interleaves_v6_first() {
+        let a4: SocketAddr = "192.0.2.1:8000".parse().unwrap();
+        let b4: SocketAddr = "192.0.2.2:8000".parse().unwrap();
+        let c4: SocketAddr = "192.0.2.3:8000".parse().unwrap();
+        let a6: SocketAddr = "[2001:db8::1]:8000".parse().unwrap();
+        assert_eq!(
+            interleave_families(vec![a4, b4, a6, c4]),
+            vec![a6, a4, b4, c4]
+        );
+    }
+
+    #[test]
+    fn connect_skips_dead_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let live = listener.local_addr().unwrap();
+        let stream = connect(&[closed_port(), live][..], Duration::from_secs(5)).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), live);
+    }
+
+    #[test]
+    fn connect_reports_failure() {
+        assert!(connect(closed_port(), Duration::from_secs(5)).is_err());
+        assert!(connect(&[][..] as &[SocketAddr], Duration::from_secs(5)).is_err());
+    }
+
+    #[cfg(all(feature = "async-client", feature = "server"))]
+    #[tokio::test]
+    async fn async_clients_play_through_a_server() {
+        use crate::server::{Server, ServerConfig};
+        use std::sync::Arc;
+
+        let any_port = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Arc::new(
+            Server::bind(ServerConfig {
+                addr: any_port,
+                #[cfg(feature = "status-page")]
+                status_addr: any_port,
+                ..ServerConfig::default()
+            })
+            .await
+            .unwrap(),
+        );
+        let running = tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        let mut a = Client::connect(server.local_addr()).await.unwrap();
+        a.handshake(None, MatchOptions::default()).await.unwrap();
+        let mut b = Client::connect(server.local_addr()).await.unwrap();
+        b.handshake(None, MatchOptions::default()).await.unwrap();
+        assert_eq!(a.is_player_two(), None);
+        assert_eq!(a.next_state().await.unwrap(), state(0, 0));
+        assert_eq!(b.next_state().await.unwrap(), state(0, 0));
+
+        // The server tells each client its side when the match starts.
+        let (mut first, mut second) = match (a.is_player_two(), b.is_player_two()) {
+            (Some(false), Some(true)) => (a, b),
+            (Some(true), Some(false)) => (b, a),
+            sides => panic!("expected one client on each side, got {:?}", sides),
+        };
+        assert_eq!(
+            second.submit_move(4).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(second.state(), state(0, 0));
+        first.submit_move(4).await.unwrap();
+        assert_eq!(second.await_opponent_move().await.unwrap(), 4);
+        assert_eq!(
+            second.submit_move(4).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        second.submit_move(0).await.unwrap();
+        assert_eq!(first.await_opponent_move().await.unwrap(), 0);
+        assert_eq!(first.state().get_o_marks(), 0b1);
+
+        drop((first, second));
+        server.shutdown();
+        running.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,584 @@
+// The decisions a connection makes about the frames it receives, without any IO.
+// The server feeds frames and state actor answers in and performs the actions that come out,
+// so every branch can be tested without opening a socket.
+use std::time::Instant;
+
+use crate::{
+    error::T3p0Error,
+    frame_type::FrameType,
+    game_state::{GameResult, GameState, GameStateTrait},
+    nack::{Nack, NackCode, NackTrait},
+    player::Player,
+    rate_limit::{TokenBucket, TokenBucketTrait},
+    request::{DataRequest, Request},
+    send_queue::FrameClass,
+};
+
+/// Something the connection wants done. Actions are performed in the order they're returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Queue a data frame for the client.
+    Send(FrameClass, Request),
+    /// Queue a NACK for the client.
+    Nack(Nack),
+    /// Ask the state actor for the player's game and pass the answer to `on_state`.
+    QueryState,
+    /// Store the game's new state and relay it to the other players in the game.
+    Apply(GameState),
+    /// Write the rejected frame to the quarantine log.
+    Quarantine { raw: Vec<u8>, reason: T3p0Error },
+    /// The move received at the given time has been answered.
+    MoveAnswered(Instant),
+    /// The move just applied finished the game. No more moves are accepted in it.
+    GameOver(GameResult),
+    /// Close the connection.
+    Close(T3p0Error),
+}
+
+#[derive(Debug)]
+pub struct ConnectionCore {
+    player: Player,
+    rate_limit: TokenBucket,
+    max_rate_limited_frames: u32,
+    rate_limited_frames: u32,
+    /// The last state sent to the client. Rate limited frames are NACKed with this instead of
+    /// asking the state actor so a flooding client can't slow down other games.
+    last_state: Request,
+    /// The frame waiting on the state actor and when it was received.
+    pending: Option<(Vec<u8>, Instant)>,
+}
+
+pub trait ConnectionCoreTrait {
+    fn new(player: Player, rate_limit: TokenBucket, max_rate_limited_frames: u32) -> Self;
+    fn on_idle(&mut self) -> Vec<Action>;
+    fn on_frame(&mut self, frame: &[u8], received: Instant) -> Vec<Action>;
+    fn on_state(&mut self, game_state: Option<GameState>) -> Vec<Action>;
+}
+
+impl ConnectionCoreTrait for ConnectionCore {
+    /// Creates the core for a player who has finished the handshake.
+    ///
+    /// # Arguments
+    ///
+    /// * `player` - The player on the other end of the connection.
+    /// * `rate_limit` - The bucket every frame takes a token from.
+    /// * `max_rate_limited_frames` - How many rate limited frames in a row close the connection.
+    fn new(player: Player, rate_limit: TokenBucket, max_rate_limited_frames: u32) -> Self {
+        ConnectionCore {
+            player,
+            rate_limit,
+            max_rate_limited_frames,
+            rate_limited_frames: 0,
+            last_state: Request::new_data_request(false),
+            pending: None,
+        }
+    }
+
+    /// The connection has been quiet for the ping interval, so send an Ok response as a ping.
+    fn on_idle(&mut self) -> Vec<Action> {
+        vec![Action::Send(
+            FrameClass::Status,
+            Request::new_data_request(true),
+        )]
+    }
+
+    /// Handles a frame from the client.
+    /// Frames that get past the rate limit need the player's game, so the answer is
+    /// `QueryState` and the frame is finished in `on_state`.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The bytes read from the socket.
+    /// * `received` - When the frame was read.
+    fn on_frame(&mut self, frame: &[u8], received: Instant) -> Vec<Action> {
+        if !self.rate_limit.try_take_at(received) {
+            self.rate_limited_frames = self.rate_limited_frames.saturating_add(1);
+            if self.rate_limited_frames >= self.max_rate_limited_frames {
+                return vec![Action::Close(T3p0Error::RateLimitExceeded)];
+            }
+            return vec![Action::Nack(Nack::new(
+                NackCode::RateLimited,
+                self.last_state,
+            ))];
+        }
+        self.rate_limited_frames = 0;
+        self.pending = Some((frame.to_vec(), received));
+        vec![Action::QueryState]
+    }
+
+    /// Finishes the pending frame with the state actor's answer.
+    ///
+    /// # Arguments
+    ///
+    /// * `game_state` - The player's game, if they have one.
+    fn on_state(&mut self, game_state: Option<GameState>) -> Vec<Action> {
+        let Some((frame, received)) = self.pending.take() else {
+            return Vec::new();
+        };
+        let authoritative = match &game_state {
+            Some(game_state) => game_state.to_request(),
+            None => Request::new_data_request(false),
+        };
+        self.last_state = authoritative;
+
+        let (frame_type, request) = match FrameType::classify(&frame) {
+            Ok(classified) => classified,
+            Err(reason) => {
+                return vec![
+                    Action::Quarantine { raw: frame, reason },
+                    Action::Nack(Nack::new(NackCode::InvalidFrame, authoritative)),
+                    Action::Close(T3p0Error::InvalidFrameLength),
+                ]
+            }
+        };
+        handler(frame_type)(
+            self,
+            Frame {
+                raw: frame,
+                request,
+                received,
+                game_state,
+                authoritative,
+            },
+        )
+    }
+}
+
+/// A classified frame along with what the state actor said about the player's game.
+struct Frame {
+    raw: Vec<u8>,
+    request: Request,
+    received: Instant,
+    game_state: Option<GameState>,
+    /// The state the server considers correct, sent back with any NACK so the client can repair its view.
+    authoritative: Request,
+}
+
+type Handler = fn(&ConnectionCore, Frame) -> Vec<Action>;
+
+/// The dispatch table. The match is exhaustive, so a new `FrameType` doesn't compile without a handler.
+fn handler(frame_type: FrameType) -> Handler {
+    match frame_type {
+        FrameType::Ok => handle_ok,
+        FrameType::Move => handle_move,
+        FrameType::DryRun => handle_dry_run,
+    }
+}
+
+/// Replies with the game state, or echoes the Ok if there isn't a game.
+/// If the user doesn't receive the ok request, they will close the connection and try again.
+fn handle_ok(_core: &ConnectionCore, frame: Frame) -> Vec<Action> {
+    let reply = match frame.game_state {
+        Some(game_state) => game_state.to_request(),
+        None => frame.request,
+    };
+    vec![Action::Send(FrameClass::Reply, reply)]
+}
+
+/// Applies a legal next move to the player's game, and ends the game if the move finished it.
+/// If the move is not a valid request, we NACK it with the authoritative state. A valid request
+/// that isn't the game's next move, a move in a finished game, or a move from a player without
+/// a game, is NACKed as illegal.
+fn handle_move(core: &ConnectionCore, frame: Frame) -> Vec<Action> {
+    if let Err(reason) = frame.request.validate_request() {
+        return vec![
+            Action::Quarantine {
+                raw: frame.raw,
+                reason,
+            },
+            Action::Nack(Nack::new(NackCode::InvalidRequest, frame.authoritative)),
+        ];
+    }
+    if let Some(current) = &frame.game_state {
+        if let Ok(candidate) = GameState::from_request(frame.request, core.player.clone()) {
+            if is_legal(current, &candidate) {
+                let mut next = candidate
+                    .with_mode(current.get_mode())
+                    .with_privacy(current.get_privacy());
+                if let Some(roster) = current.get_roster() {
+                    next = next.with_roster(roster.clone());
+                }
+                let result = next.check_winner();
+                let mut actions = vec![
+                    Action::Apply(next),
+                    Action::Send(FrameClass::Reply, frame.request),
+                    Action::MoveAnswered(frame.received),
+                ];
+                if result != GameResult::InProgress {
+                    actions.push(Action::GameOver(result));
+                }
+                return actions;
+            }
+        }
+    }
+    vec![
+        Action::Nack(Nack::new(NackCode::IllegalMove, frame.authoritative)),
+        Action::MoveAnswered(frame.received),
+    ]
+}
+
+/// A dry run only asks whether the move would be legal. It's never applied, and an
+/// illegal answer is expected so the frame isn't quarantined. Like a move, it's illegal
+/// without a game.
+fn handle_dry_run(core: &ConnectionCore, frame: Frame) -> Vec<Action> {
+    let legal = GameState::from_request(frame.request.set_dry_run(false), core.player.clone())
+        .is_ok_and(|candidate| match &frame.game_state {
+            Some(current) => is_legal(current, &candidate),
+            None => false,
+        });
+    if legal {
+        return vec![Action::Send(
+            FrameClass::Reply,
+            Request::new_data_request(true),
+        )];
+    }
+    vec![Action::Nack(Nack::new(
+        NackCode::IllegalMove,
+        frame.authoritative,
+    ))]
+}
+
+/// Whether `candidate` is the next move of the unfinished game `current`.
+fn is_legal(current: &GameState, candidate: &GameState) -> bool {
+    current.check_winner() == GameResult::InProgress && current.validate_turn(candidate).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::PlayerTrait;
+
+    const FIRST_MOVE: u32 = 0x0c20_0010;
+
+    fn core(capacity: u32, max_rate_limited_frames: u32) -> ConnectionCore {
+        ConnectionCore::new(
+            Player::from_bytes(&[1; 16]),
+            TokenBucket::new(capacity, 1),
+            max_rate_limited_frames,
+        )
+    }
+
+    fn frame(request: u32) -> [u8; 4] {
+        request.to_be_bytes()
+    }
+
+    #[test]
+    fn idle_sends_status_ping() {
+        assert_eq!(
+            core(1, 1).on_idle(),
+            vec![Action::Send(
+                FrameClass::Status,
+                Request::new_data_request(true)
+            )]
+        );
+    }
+
+    #[test]
+    fn frame_queries_state() {
+        let mut core = core(1, 1);
+        assert_eq!(
+            core.on_frame(&frame(FIRST_MOVE), Instant::now()),
+            vec![Action::QueryState]
+        );
+    }
+
+    #[test]
+    fn state_without_pending_frame_does_nothing() {
+        assert!(core(1, 1).on_state(None).is_empty());
+    }
+
+    #[test]
+    fn move_without_game_is_nacked() {
+        let mut core = core(1, 1);
+        let received = Instant::now();
+        core.on_frame(&frame(FIRST_MOVE), received);
+        assert_eq!(
+            core.on_state(None),
+            vec![
+                Action::Nack(Nack::new(
+                    NackCode::IllegalMove,
+                    Request::new_data_request(false)
+                )),
+                Action::MoveAnswered(received),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_move_is_nacked_with_game_state() {
+        let mut core = core(1, 1);
+        let game_state =
+            GameState::from_request(Request(FIRST_MOVE), Player::from_bytes(&[2; 16])).unwrap();
+        core.on_frame(&frame(FIRST_MOVE), Instant::now());
+        let actions = core.on_state(Some(game_state));
+        assert_eq!(
+            actions[0],
+            Action::Nack(Nack::new(NackCode::IllegalMove, Request(FIRST_MOVE)))
+        );
+        assert!(matches!(actions[1], Action::MoveAnswered(_)));
+    }
+
+    #[test]
+    fn legal_move_is_applied() {
+        let mut core = core(1, 1);
+        let game = crate::matchmaking::new_match(
+            Player::from_bytes(&[1; 16]),
+            Player::from_bytes(&[3; 16]),
+        );
+        let received = Instant::now();
+        core.on_frame(&frame(FIRST_MOVE), received);
+        let actions = core.on_state(Some(game.clone()));
+        let Action::Apply(next) = &actions[0] else {
+            panic!("expected Apply, got {:?}", actions[0]);
+        };
+        assert_eq!(next.to_request(), Request(FIRST_MOVE));
+        assert_eq!(next.get_roster(), game.get_roster());
+        assert_eq!(
+            actions[1..],
+            [
+                Action::Send(FrameClass::Reply, Request(FIRST_MOVE)),
+                Action::MoveAnswered(received),
+            ]
+        );
+    }
+
+    fn data(turn: u8, board: u16, o_marks: u16) -> Request {
+        Request::new_data_request(false)
+            .set_turn(turn)
+            .set_message_number(turn)
+            .set_p2_turn(turn % 2 == 1)
+            .set_board(board)
+            .set_o_marks(o_marks)
+    }
+
+    /// X on the top left and top middle, O on the two squares below them, X to move.
+    fn game_before_win() -> GameState {
+        let first = Player::from_bytes(&[1; 16]);
+        let second = Player::from_bytes(&[2; 16]);
+        let game = crate::matchmaking::new_match(first, second.clone());
+        GameState::from_request(data(4, 0b11_011, 0b11_000), second)
+            .unwrap()
+            .with_roster(game.get_roster().unwrap().clone())
+    }
+
+    #[test]
+    fn winning_move_ends_the_game() {
+        let mut core = core(1, 1);
+        let received = Instant::now();
+        let win = data(5, 0b11_111, 0b11_000);
+        core.on_frame(&frame(win.0), received);
+        let actions = core.on_state(Some(game_before_win()));
+        assert!(matches!(actions[0], Action::Apply(_)));
+        assert_eq!(
+            actions[1..],
+            [
+                Action::Send(FrameClass::Reply, win),
+                Action::MoveAnswered(received),
+                Action::GameOver(GameResult::XWins),
+            ]
+        );
+    }
+
+    /// Plays `squares` in order through one core per player, X first, starting from a new match.
+    /// Returns the actions for the last move.
+    fn play(squares: &[u16]) -> Vec<Action> {
+        let first = Player::from_bytes(&[1; 16]);
+        let second = Player::from_bytes(&[2; 16]);
+        let mut cores = [
+            ConnectionCore::new(first.clone(), TokenBucket::new(9, 1), 1),
+            ConnectionCore::new(second.clone(), TokenBucket::new(9, 1), 1),
+        ];
+        let mut game = crate::matchmaking::new_match(first, second);
+        let mut request = game.to_request();
+        let mut actions = Vec::new();
+        for (i, square) in squares.iter().enumerate() {
+            request = request.increment_turn_and_message().unwrap();
+            request = request.set_board(request.get_board_state() | 1 << square);
+            if i % 2 == 1 {
+                request = request.set_o_marks(request.get_o_marks() | 1 << square);
+            }
+            let core = &mut cores[i % 2];
+            core.on_frame(&frame(request.0), Instant::now());
+            actions = core.on_state(Some(game));
+            let Action::Apply(next) = &actions[0] else {
+                panic!("move {} was not applied: {:?}", i + 1, actions[0]);
+            };
+            game = next.clone();
+        }
+        actions
+    }
+
+    #[test]
+    fn full_game_ends_in_a_draw() {
+        // X O X
+        // X O O
+        // O X X
+        let actions = play(&[0, 1, 2, 4, 3, 5, 7, 6, 8]);
+        assert_eq!(actions.last(), Some(&Action::GameOver(GameResult::Draw)));
+    }
+
+    #[test]
+    fn ninth_move_can_win() {
+        // X X O
+        // O X O
+        // X O X, with the last X completing the diagonal
+        let actions = play(&[0, 2, 1, 3, 4, 7, 6, 5, 8]);
+        assert_eq!(actions.last(), Some(&Action::GameOver(GameResult::XWins)));
+    }
+
+    #[test]
+    fn move_in_finished_game_is_nacked() {
+        let first = Player::from_bytes(&[1; 16]);
+        let won = GameState::from_request(data(5, 0b11_111, 0b11_000), first)
+            .unwrap()
+            .with_roster(game_before_win().get_roster().unwrap().clone());
+        let mut core = ConnectionCore::new(Player::from_bytes(&[2; 16]), TokenBucket::new(1, 1), 1);
+        core.on_frame(&frame(data(6, 0b111_111, 0b111_000).0), Instant::now());
+        assert_eq!(
+            core.on_state(Some(won.clone()))[0],
+            Action::Nack(Nack::new(NackCode::IllegalMove, won.to_request()))
+        );
+    }
+
+    #[test]
+    fn move_out_of_turn_is_nacked() {
+        let mut core = core(1, 1);
+        let game = crate::matchmaking::new_match(
+            Player::from_bytes(&[3; 16]),
+            Player::from_bytes(&[1; 16]),
+        );
+        core.on_frame(&frame(FIRST_MOVE), Instant::now());
+        let actions = core.on_state(Some(game));
+        assert_eq!(
+            actions[0],
+            Action::Nack(Nack::new(
+                NackCode::IllegalMove,
+                Request::new_data_request(false)
+            ))
+        );
+    }
+
+    #[test]
+    fn ok_is_echoed_without_latency() {
+        let mut core = core(1, 1);
+        core.on_frame(&frame(1 << 31), Instant::now());
+        assert_eq!(
+            core.on_state(None),
+            vec![Action::Send(
+                FrameClass::Reply,
+                Request::new_data_request(true)
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_request_is_quarantined_and_nacked() {
+        let mut core = core(1, 1);
+        // Turn 1 with an empty board.
+        let request = Request::new_data_request(false)
+            .set_turn(1)
+            .set_message_number(1)
+            .set_p2_turn(true);
+        core.on_frame(&frame(request.0), Instant::now());
+        assert_eq!(
+            core.on_state(None),
+            vec![
+                Action::Quarantine {
+                    raw: frame(request.0).to_vec(),
+                    reason: T3p0Error::FirstMoveNotOneMark,
+                },
+                Action::Nack(Nack::new(
+                    NackCode::InvalidRequest,
+                    Request::new_data_request(false)
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_length_closes() {
+        let mut core = core(1, 1);
+        core.on_frame(&[1, 2], Instant::now());
+        assert_eq!(
+            core.on_state(None),
+            vec![
+                Action::Quarantine {
+                    raw: vec![1, 2],
+                    reason: T3p0Error::InvalidFrameLength,
+                },
+                Action::Nack(Nack::new(
+                    NackCode::InvalidFrame,
+                    Request::new_data_request(false)
+                )),
+                Action::Close(T3p0Error::InvalidFrameLength),
+            ]
+        );
+    }
+
+    #[test]
+    fn dry_run_legal_and_illegal() {
+        let mut core = core(3, 1);
+        let game = crate::matchmaking::new_match(
+            Player::from_bytes(&[1; 16]),
+            Player::from_bytes(&[3; 16]),
+        );
+        let now = Instant::now();
+        core.on_frame(&frame(Request(FIRST_MOVE).set_dry_run(true).0), now);
+        assert_eq!(
+            core.on_state(Some(game.clone())),
+            vec![Action::Send(
+                FrameClass::Reply,
+                Request::new_data_request(true)
+            )]
+        );
+        // Two marks on the first move.
+        core.on_frame(&frame(Request(FIRST_MOVE | 1).set_dry_run(true).0), now);
+        assert_eq!(
+            core.on_state(Some(game)),
+            vec![Action::Nack(Nack::new(
+                NackCode::IllegalMove,
+                Request::new_data_request(false)
+            ))]
+        );
+        // There is no game to move in.
+        core.on_frame(&frame(Request(FIRST_MOVE).set_dry_run(true).0), now);
+        assert_eq!(
+            core.on_state(None),
+            vec![Action::Nack(Nack::new(
+                NackCode::IllegalMove,
+                Request::new_data_request(false)
+            ))]
+        );
+    }
+
+    #[test]
+    fn rate_limited_frames_are_nacked_with_last_state_then_closed() {
+        let mut core = core(1, 3);
+        let now = Instant::now();
+        let game_state =
+            GameState::from_request(Request(FIRST_MOVE), Player::from_bytes(&[2; 16])).unwrap();
+        core.on_frame(&frame(FIRST_MOVE), now);
+        core.on_state(Some(game_state));
+
+        let nack = vec![Action::Nack(Nack::new(
+            NackCode::RateLimited,
+            Request(FIRST_MOVE),
+        ))];
+        assert_eq!(core.on_frame(&frame(FIRST_MOVE), now), nack);
+        assert_eq!(core.on_frame(&frame(FIRST_MOVE), now), nack);
+        assert_eq!(
+            core.on_frame(&frame(FIRST_MOVE), now),
+            vec![Action::Close(T3p0Error::RateLimitExceeded)]
+        );
+    }
+
+    #[test]
+    fn rate_limited_count_saturates() {
+        let mut core = core(0, u32::MAX);
+        core.rate_limited_frames = u32::MAX - 1;
+        let now = Instant::now();
+        let close = vec![Action::Close(T3p0Error::RateLimitExceeded)];
+        assert_eq!(core.on_frame(&frame(FIRST_MOVE), now), close);
+        assert_eq!(core.on_frame(&frame(FIRST_MOVE), now), close);
+    }
+}
@@ -0,0 +1,163 @@
+// Finding servers on the local network.
+// A client sends a query to a multicast group and every server listening on the group answers
+// it directly with its name, game port and version, so players don't have to type addresses.
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// The multicast group servers listen on. It's in the organisation-local scope so it stays on the LAN.
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 84, 48);
+/// The UDP port servers listen on for queries.
+pub const DISCOVERY_PORT: u16 = 8002;
+/// What a client sends to ask servers to announce themselves.
+pub const DISCOVERY_QUERY: &[u8] = b"T3P0 DISCOVER";
+
+const ANNOUNCEMENT_PREFIX: &str = "T3P0 SERVER";
+
+/// A server's answer to a discovery query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub name: String,
+    /// Where the game listener accepts players.
+    pub address: SocketAddr,
+    pub version: String,
+}
+
+pub trait AnnouncementTrait {
+    fn new(name: &str, port: u16) -> Self;
+    fn to_datagram(&self) -> Vec<u8>;
+    fn from_datagram(datagram: &[u8], source: IpAddr) -> Result<Self, &'static str>
+    where
+        Self: Sized;
+}
+
+impl AnnouncementTrait for Announcement {
+    /// Creates the announcement for this build of the server.
+    /// The address is filled in by the client from where the answer came from.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name shown to players.
+    /// * `port` - The game port.
+    fn new(name: &str, port: u16) -> Self {
+        Announcement {
+            name: name.to_string(),
+            address: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Encodes the announcement as `T3P0 SERVER <version> <port> <name>`.
+    /// The name goes last so it may contain spaces.
+    fn to_datagram(&self) -> Vec<u8> {
+        format!(
+            "{} {} {} {}",
+            ANNOUNCEMENT_PREFIX,
+            self.version,
+            self.address.port(),
+            self.name
+        )
+        .into_bytes()
+    }
+
+    /// Decodes an announcement.
+    ///
+    /// # Arguments
+    ///
+    /// * `datagram` - The bytes received.
+    /// * `source` - The address the datagram came from, which is where the server is.
+    ///
+    /// # Errors
+    ///
+    /// * `&'static str` - If the datagram isn't an announcement.
+    fn from_datagram(datagram: &[u8], source: IpAddr) -> Result<Self, &'static str> {
+        let text = std::str::from_utf8(datagram).map_err(|_| "Announcement isn't UTF-8.")?;
+        let rest = text
+            .strip_prefix(ANNOUNCEMENT_PREFIX)
+            .and_then(|rest| rest.strip_prefix(' '))
+            .ok_or("Not a T3P0 announcement.")?;
+        let mut parts = rest.splitn(3, ' ');
+        let version = parts.next().filter(|version| !version.is_empty());
+        let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+        let name = parts.next();
+        match (version, port, name) {
+            (Some(version), Some(port), Some(name)) => Ok(Announcement {
+                name: name.to_string(),
+                address: SocketAddr::new(source, port),
+                version: version.to_string(),
+            }),
+            _ => Err("Malformed T3P0 announcement."),
+        }
+    }
+}
+
+/// Asks the local network for servers and collects the answers until `timeout` passes.
+/// A server that answers more than once is only listed once.
+///
+/// # Arguments
+///
+/// * `timeout` - How long to wait for answers.
+///
+/// # Errors
+///
+/// * `io::Error` - If the query can't be sent.
+pub fn discover_servers(timeout: Duration) -> io::Result<Vec<Announcement>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_multicast_ttl_v4(1)?;
+    socket.send_to(DISCOVERY_QUERY, (DISCOVERY_GROUP, DISCOVERY_PORT))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut servers: Vec<Announcement> = Vec::new();
+    let mut buffer = [0u8; 512];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (n, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e),
+        };
+        if let Ok(server) = Announcement::from_datagram(&buffer[..n], source.ip()) {
+            if !servers.contains(&server) {
+                servers.push(server);
+            }
+        }
+    }
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    #[test]
+    fn round_trip() {
+        let announcement = Announcement::new("Living room server", 8000);
+        let decoded = Announcement::from_datagram(&announcement.to_datagram(), SOURCE).unwrap();
+        assert_eq!(decoded.name, "Living room server");
+        assert_eq!(decoded.address, SocketAddr::new(SOURCE, 8000));
+        assert_eq!(decoded.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn rejects_other_datagrams() {
+        assert!(Announcement::from_datagram(DISCOVERY_QUERY, SOURCE).is_err());
+        assert!(Announcement::from_datagram(b"T3P0 SERVER 0.1.0 port name", SOURCE).is_err());
+        assert!(Announcement::from_datagram(b"T3P0 SERVER 0.1.0 8000", SOURCE).is_err());
+        assert!(Announcement::from_datagram(&[0xff, 0xfe], SOURCE).is_err());
+    }
+}
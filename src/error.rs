@@ -0,0 +1,81 @@
+// Why a frame, a move or a connection was rejected.
+// Each rule has its own variant so callers can match on the reason instead of comparing strings.
+// The messages are what ends up in logs and the quarantine.
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum T3p0Error {
+    /// The message number is past the last message of a game, or would be after incrementing.
+    #[error("Trying to increment message number past maximum value.")]
+    MessageNumberOverflow,
+    /// The turn number is past the last turn of a game, or would be after incrementing.
+    #[error("Trying to increment turn number past maximum value.")]
+    TurnOverflow,
+    /// An O mark is set on a square the board says is empty.
+    #[error("O marks must be on filled squares.")]
+    OMarkOnEmptySquare,
+    #[error("Message number is less than turn number.")]
+    MessageBehindTurn,
+    /// The turn number isn't the message number mod 9.
+    #[error("Turn number and message number are not in sync.")]
+    TurnOutOfSync,
+    /// The P2 turn bit is set on an even message number.
+    #[error("Player 2 is trying to make a move on player 1's turn.")]
+    PlayerTwoOutOfTurn,
+    /// The P2 turn bit is clear on an odd message number.
+    #[error("Player 1 is trying to make a move on player 2's turn.")]
+    PlayerOneOutOfTurn,
+    #[error("A new game must start with an empty board.")]
+    BoardNotEmptyAtStart,
+    #[error("The first move must place exactly one mark.")]
+    FirstMoveNotOneMark,
+    /// The move's turn, message number or side doesn't follow on from the game's current state.
+    #[error("The move is not the next move of the game.")]
+    NotNextMove,
+    /// The move was made by a player whose turn it isn't, including the one who made the last move.
+    #[error("It is not this player's turn.")]
+    WrongPlayerTurn,
+    /// The move changed a mark that was already placed, or didn't place exactly one new mark.
+    #[error("A move must place exactly one mark on an empty square.")]
+    IllegalBoardChange,
+    /// The frame wasn't a whole request.
+    #[error("Invalid frame length.")]
+    InvalidFrameLength,
+    /// The connection had too many frames in a row dropped by the rate limiter.
+    #[error("Connection exceeded the frame rate limit.")]
+    RateLimitExceeded,
+    /// The client closed the connection during the handshake, or a frame was queued for a
+    /// connection that is already closed.
+    #[error("Connection closed.")]
+    ConnectionClosed,
+    /// The client sent something other than an Ok or a player id during the handshake.
+    #[error("Invalid handshake message.")]
+    InvalidHandshake,
+    /// A frame didn't fit in a send queue that disconnects when it is full.
+    #[error("The send queue is full.")]
+    SendQueueFull,
+    /// A reply didn't fit in a send queue that holds nothing but replies.
+    #[error("The send queue is full of replies the client hasn't read.")]
+    UnreadReplies,
+    /// The server stopped accepting players.
+    #[error("Shutting down.")]
+    ShuttingDown,
+    /// The task that owns the games has stopped.
+    #[error("The state actor has stopped.")]
+    StateActorStopped,
+    /// The task that owns the games didn't answer before the deadline.
+    #[error("The state actor didn't answer in time.")]
+    StateActorTimeout,
+    /// A roster was given a team with no players.
+    #[error("Both teams need at least one player.")]
+    EmptyTeam,
+    /// A roster listed the same player twice.
+    #[error("A player can only be listed once in a roster.")]
+    DuplicatePlayer,
+    /// The frame's header doesn't have the NACK flag set.
+    #[error("Frame is not a NACK.")]
+    NotANack,
+    /// The NACK's error code isn't one this crate knows.
+    #[error("Unknown NACK error code.")]
+    UnknownNackCode,
+}
@@ -0,0 +1,156 @@
+// Logging for the move path.
+// Events are small `Copy` structs pushed into a bounded channel that is allocated once up front.
+// Recording an event never allocates or waits: if the logger falls behind the event is dropped
+// and counted instead. Formatting happens on the logger's own thread.
+use std::{
+    fmt,
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    time::Duration,
+};
+
+use crate::{
+    nack::NackCode,
+    player::{Player, PlayerTrait},
+    request::Request,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogEventKind {
+    /// A move was answered. `elapsed` is how long it took.
+    MoveAnswered,
+    /// A NACK was sent.
+    NackSent(NackCode),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogEvent {
+    pub kind: LogEventKind,
+    /// The player's id as bytes so the event doesn't hold any heap data.
+    pub player: [u8; 16],
+    /// The frame the client sent, or the state sent back with a NACK.
+    pub frame: u32,
+    pub elapsed: Duration,
+}
+
+impl LogEvent {
+    pub fn new(kind: LogEventKind, player: &Player, frame: Request, elapsed: Duration) -> Self {
+        LogEvent {
+            kind,
+            player: *player.get_id().as_bytes(),
+            frame: frame.0,
+            elapsed,
+        }
+    }
+}
+
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let player = uuid::Uuid::from_bytes(self.player);
+        match self.kind {
+            LogEventKind::MoveAnswered => write!(
+                f,
+                "move player={} frame={:08x} elapsed_us={}",
+                player,
+                self.frame,
+                self.elapsed.as_micros()
+            ),
+            LogEventKind::NackSent(code) => write!(
+                f,
+                "nack player={} code={:?} state={:08x}",
+                player, code, self.frame
+            ),
+        }
+    }
+}
+
+/// The recording side of the log. Cheap to clone, one per connection.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    sender: SyncSender<LogEvent>,
+}
+
+pub trait EventLogTrait {
+    fn new(capacity: usize) -> (Self, Receiver<LogEvent>)
+    where
+        Self: Sized;
+    fn record(&self, event: LogEvent) -> bool;
+}
+
+impl EventLogTrait for EventLog {
+    /// Creates the log and the receiver the logger drains.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - How many events can wait for the logger before new ones are dropped.
+    fn new(capacity: usize) -> (Self, Receiver<LogEvent>) {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        (EventLog { sender }, receiver)
+    }
+
+    /// Records an event without blocking.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - False if the event was dropped because the log is full or the logger has stopped.
+    fn record(&self, event: LogEvent) -> bool {
+        match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(frame: u32) -> LogEvent {
+        LogEvent::new(
+            LogEventKind::MoveAnswered,
+            &Player::from_bytes(&[0; 16]),
+            Request(frame),
+            Duration::from_micros(250),
+        )
+    }
+
+    #[test]
+    fn events_arrive_in_order() {
+        let (log, receiver) = EventLog::new(4);
+        assert!(log.record(event(1)));
+        assert!(log.record(event(2)));
+        assert_eq!(receiver.try_recv(), Ok(event(1)));
+        assert_eq!(receiver.try_recv(), Ok(event(2)));
+    }
+
+    #[test]
+    fn full_log_drops_events() {
+        let (log, receiver) = EventLog::new(1);
+        assert!(log.record(event(1)));
+        assert!(!log.record(event(2)));
+        assert_eq!(receiver.try_recv(), Ok(event(1)));
+        assert!(log.record(event(3)));
+    }
+
+    #[test]
+    fn stopped_logger_drops_events() {
+        let (log, receiver) = EventLog::new(1);
+        drop(receiver);
+        assert!(!log.record(event(1)));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            event(0x0c200010).to_string(),
+            "move player=00000000-0000-0000-0000-000000000000 frame=0c200010 elapsed_us=250"
+        );
+        let nack = LogEvent {
+            kind: LogEventKind::NackSent(NackCode::RateLimited),
+            ..event(0)
+        };
+        assert_eq!(
+            nack.to_string(),
+            "nack player=00000000-0000-0000-0000-000000000000 code=RateLimited state=00000000"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Every kind of frame a client can send once the handshake is done.
+// A new frame type is added here first, and the connection core's dispatch table then fails to
+// compile until it has a handler.
+use crate::{
+    error::T3p0Error,
+    request::{DataRequest, Request},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameType {
+    /// An Ok response. Asks for the current state and answers pings.
+    Ok,
+    /// A move.
+    Move,
+    /// A move the client only wants checked, see `DataRequest::is_dry_run`.
+    DryRun,
+}
+
+impl FrameType {
+    pub const ALL: [FrameType; 3] = [FrameType::Ok, FrameType::Move, FrameType::DryRun];
+
+    /// Works out what kind of frame the client sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The bytes read from the socket.
+    ///
+    /// # Returns
+    ///
+    /// * `(FrameType, Request)` - The frame type and the decoded request.
+    ///
+    /// # Errors
+    ///
+    /// * `T3p0Error::InvalidFrameLength` - If the frame isn't a whole request.
+    pub fn classify(frame: &[u8]) -> Result<(FrameType, Request), T3p0Error> {
+        let bytes = <[u8; 4]>::try_from(frame).map_err(|_| T3p0Error::InvalidFrameLength)?;
+        let request = Request(u32::from_be_bytes(bytes));
+        let frame_type = if request.is_ok_response() {
+            FrameType::Ok
+        } else if request.is_dry_run() {
+            FrameType::DryRun
+        } else {
+            FrameType::Move
+        };
+        Ok((frame_type, request))
+    }
+
+    /// A short name for logs and docs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameType::Ok => "Ok",
+            FrameType::Move => "Move",
+            FrameType::DryRun => "Dry Run",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let ok = Request::new_data_request(true);
+        let first_move = Request(0x0c20_0010);
+        assert_eq!(
+            FrameType::classify(&ok.0.to_be_bytes()),
+            Ok((FrameType::Ok, ok))
+        );
+        assert_eq!(
+            FrameType::classify(&first_move.0.to_be_bytes()),
+            Ok((FrameType::Move, first_move))
+        );
+        let dry_run = first_move.set_dry_run(true);
+        assert_eq!(
+            FrameType::classify(&dry_run.0.to_be_bytes()),
+            Ok((FrameType::DryRun, dry_run))
+        );
+        assert!(FrameType::classify(&[0x80, 0, 0]).is_err());
+        assert!(FrameType::classify(&[0x80, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (i, a) in FrameType::ALL.iter().enumerate() {
+            for b in &FrameType::ALL[i + 1..] {
+                assert_ne!(a.name(), b.name());
+            }
+        }
+    }
+}
@@ -1,47 +1,174 @@
 use crate::{
+    error::T3p0Error,
     request::{DataRequest, Request},
+    roster::{Roster, RosterTrait},
     Player, PlayerTrait,
 };
 
-#[derive(Debug, Clone)]
+/// How the players of a game are checked when a turn is validated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameMode {
+    /// Two different players take turns.
+    #[default]
+    Standard,
+    /// A single connection plays both sides, for practicing or demoing UIs.
+    /// The board is still validated but the submitting player is not.
+    Sandbox,
+}
+
+/// Who may watch a game on the observer port. Settings are ordered from the least to the most
+/// private, so the stricter of two is the greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Privacy {
+    /// Anyone may watch, and the game may be listed once there is a lobby.
+    #[default]
+    Public,
+    /// Anyone who knows a player's id may watch but the game is never listed.
+    Unlisted,
+    /// Nobody may watch.
+    Private,
+}
+
+impl Privacy {
+    /// The name used in session files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Privacy::Public => "public",
+            Privacy::Unlisted => "unlisted",
+            Privacy::Private => "private",
+        }
+    }
+
+    /// Parses a name written by `name`.
+    ///
+    /// # Errors
+    ///
+    /// * `&'static str` - If the name isn't public, unlisted or private.
+    pub fn from_name(name: &str) -> Result<Self, &'static str> {
+        match name {
+            "public" => Ok(Privacy::Public),
+            "unlisted" => Ok(Privacy::Unlisted),
+            "private" => Ok(Privacy::Private),
+            _ => Err("Unknown privacy, expected public, unlisted or private."),
+        }
+    }
+}
+
+/// How a game stands, see `GameStateTrait::check_winner`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    /// The first player completed a line.
+    XWins,
+    /// The second player completed a line.
+    OWins,
+    /// The board is full and nobody completed a line.
+    Draw,
+    InProgress,
+}
+
+/// The value of a square in `GameState::board` holding the first player's mark.
+pub(crate) const X_MARK: u8 = 1;
+/// The value of a square in `GameState::board` holding the second player's mark.
+pub(crate) const O_MARK: u8 = 2;
+
+/// The mark placed by the move with the given message number. The first player makes the odd
+/// numbered moves, the same side as the `P2Turn` bit of a valid request.
+pub(crate) fn mark_for(message_number: u8) -> u8 {
+    if message_number % 2 == 1 {
+        X_MARK
+    } else {
+        O_MARK
+    }
+}
+
+/// Every row, column and diagonal as a mask over the board, laid out like `get_board_state`.
+const LINES: [u16; 8] = [
+    0b000_000_111,
+    0b000_111_000,
+    0b111_000_000,
+    0b001_001_001,
+    0b010_010_010,
+    0b100_100_100,
+    0b100_010_001,
+    0b001_010_100,
+];
+
+/// Works out how a game stands from each player's marks.
+/// A board where both players have a line can't come from legal play, X is checked first.
+///
+/// # Arguments
+///
+/// * `x_marks` - The squares holding the first player's marks.
+/// * `o_marks` - The squares holding the second player's marks.
+///
+/// # Returns
+///
+/// * `GameResult` - The winner, a draw if the board is full, or `InProgress`.
+pub fn result_of(x_marks: u16, o_marks: u16) -> GameResult {
+    let has_line = |marks: u16| LINES.into_iter().any(|line| marks & line == line);
+    if has_line(x_marks) {
+        GameResult::XWins
+    } else if has_line(o_marks) {
+        GameResult::OWins
+    } else if (x_marks | o_marks) & 0x1FF == 0x1FF {
+        GameResult::Draw
+    } else {
+        GameResult::InProgress
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct GameState {
-    players: Option<Box<[Player; 2]>>,
+    players: Option<Box<Roster>>,
     submitted_by: Player,
     board: [u8; 9],
     turn: u8,
     message_number: u8,
     p2_turn: bool,
     request: Request,
+    mode: GameMode,
+    privacy: Privacy,
 }
 
 impl GameState {}
 
 pub trait GameStateTrait {
     fn new(player: Option<Player>, players: Option<[Player; 2]>) -> Self;
-    fn from_request(request: Request, player: Player) -> Result<Self, &'static str>
+    fn from_request(request: Request, player: Player) -> Result<Self, T3p0Error>
     where
         Self: Sized;
     fn compare_boards(&self, other: &GameState) -> bool;
-    fn validate_turn(&self, game_state: &Self) -> Result<bool, &'static str>;
+    fn validate_turn(&self, game_state: &Self) -> Result<(), T3p0Error>;
     fn to_request(&self) -> Request;
+    fn with_mode(self, mode: GameMode) -> Self;
+    fn get_mode(&self) -> GameMode;
+    fn with_privacy(self, privacy: Privacy) -> Self;
+    fn with_roster(self, roster: Roster) -> Self;
+    fn get_roster(&self) -> Option<&Roster>;
+    fn get_privacy(&self) -> Privacy;
+    fn allows_spectators(&self) -> bool;
+    fn check_winner(&self) -> GameResult;
 }
 
 impl GameStateTrait for GameState {
     fn new(player: Option<Player>, players: Option<[Player; 2]>) -> Self {
         GameState {
-            players: match players {
-                Some(p) => Some(Box::new(p)),
-                None => None,
-            },
+            players: players.map(|players| Box::new(Roster::from(players))),
             submitted_by: match player {
                 Some(p) => p,
+                #[cfg(feature = "rand")]
                 None => Player::new(),
+                // Without a random source an anonymous game is submitted by the nil player.
+                #[cfg(not(feature = "rand"))]
+                None => Player::from_bytes(&[0u8; 16]),
             },
             turn: 0,
             p2_turn: true,
             message_number: 0,
             board: [0u8; 9],
             request: Request::new_data_request(false),
+            mode: GameMode::Standard,
+            privacy: Privacy::default(),
         }
     }
 
@@ -53,14 +180,22 @@ impl GameStateTrait for GameState {
     ///
     /// # Returns
     ///
-    /// * `Option<Self>` - A new GameState if the request is valid, None otherwise
-    fn from_request(request: Request, player: Player) -> Result<Self, &'static str> {
+    /// * `Self` - A new GameState if the request is valid
+    ///
+    /// # Errors
+    ///
+    /// * `T3p0Error` - The rule the request breaks, see `DataRequest::validate_request`.
+    fn from_request(request: Request, player: Player) -> Result<Self, T3p0Error> {
         request.validate_request()?;
 
         let mut board = [0u8; 9];
-        let board_state = request.get_board_state();
+        let (x_marks, o_marks) = (request.get_x_marks(), request.get_o_marks());
         for (i, item) in board.iter_mut().enumerate() {
-            *item = (board_state >> i) as u8 & 1;
+            if (x_marks >> i) & 1 == 1 {
+                *item = X_MARK;
+            } else if (o_marks >> i) & 1 == 1 {
+                *item = O_MARK;
+            }
         }
 
         Ok(GameState {
@@ -71,6 +206,8 @@ impl GameStateTrait for GameState {
             message_number: request.get_message_number(),
             p2_turn: request.get_is_p2_turn(),
             request,
+            mode: GameMode::Standard,
+            privacy: Privacy::default(),
         })
     }
 
@@ -102,12 +239,16 @@ impl GameStateTrait for GameState {
     /// Validate a turn to see if it is a valid move
     ///
     /// For a turn to be valid, the following conditions must be met:
-    /// 1. The turn must be incremented by 1.
+    /// 1. The turn must be incremented by 1, wrapping from 8 back to 0 on the ninth move.
     /// 2. The player that submitted the new game state must be different from the player that submitted the previous game state.
     /// 3. The message number must be incremented by 1.
-    /// 4. The new game state must be submitted by one of the players.
-    /// This value is going to come from the TCP connection.
+    /// 4. The new game state must be submitted by the player whose move it is in the roster.
+    ///    This value is going to come from the TCP connection.
     /// 5. The board must be a valid move.
+    /// 6. The new mark must be X on odd message numbers and O on even ones, see `mark_for`.
+    /// 7. The first move of a game must place exactly one mark.
+    ///
+    /// In `GameMode::Sandbox` conditions 2 and 4 are skipped since one connection plays both sides.
     ///
     /// # Arguments
     ///
@@ -115,49 +256,147 @@ impl GameStateTrait for GameState {
     ///
     /// # Errors
     ///
-    /// * `&'static str` - If the turn is not valid, the error message will describe why.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<bool, &'static str>` - True if the turn is valid, false otherwise
-    fn validate_turn(&self, game_state: &Self) -> Result<bool, &'static str> {
-        // If the turn is not the next turn, it is not a valid turn
-        if self.turn + 1 != game_state.turn {
-            return Ok(false);
+    /// * `T3p0Error::NotNextMove` - If the turn, side or message number doesn't follow on.
+    /// * `T3p0Error::WrongPlayerTurn` - If the move was submitted by a player whose move it isn't.
+    /// * `T3p0Error::IllegalBoardChange` - If the board isn't the current board plus one mark of
+    ///   the moving side.
+    /// * `T3p0Error::FirstMoveNotOneMark` - If the first move doesn't place exactly one mark.
+    fn validate_turn(&self, game_state: &Self) -> Result<(), T3p0Error> {
+        // If the turn is not the next turn, it is not a valid turn.
+        // The turn field wraps to 0 on the ninth move, the message number check below keeps that
+        // from being mistaken for the start of a game.
+        if self.turn.checked_add(1).map(|turn| turn % 9) != Some(game_state.turn) {
+            return Err(T3p0Error::NotNextMove);
         }
         // If the player is the same, it is not a valid turn
         if self.p2_turn == game_state.p2_turn {
-            return Ok(false);
+            return Err(T3p0Error::NotNextMove);
         }
         // If the message number is not the next message number, it is not a valid turn
-        if self.message_number + 1 != game_state.message_number {
-            return Ok(false);
+        if self.message_number.checked_add(1) != Some(game_state.message_number) {
+            return Err(T3p0Error::NotNextMove);
         }
         // If the new game state is submitted by the same player, it is not a valid turn
-        if self.submitted_by.get_id() == game_state.submitted_by.get_id() {
-            return Ok(false);
+        if self.mode != GameMode::Sandbox
+            && self.submitted_by.get_id() == game_state.submitted_by.get_id()
+        {
+            return Err(T3p0Error::WrongPlayerTurn);
         }
-        // Check if the new game state submitted by is one of the players
-        if self.players.is_some()
-            && !self
-                .players
-                .as_ref()
-                .unwrap()
-                .contains(&game_state.submitted_by)
+        // Check the new game state was submitted by the roster member whose move it is.
+        // For a team game this also stops a teammate from moving out of rotation.
+        if self.mode != GameMode::Sandbox
+            && self.players.as_ref().is_some_and(|roster| {
+                roster.to_move(game_state.message_number) != &game_state.submitted_by
+            })
         {
-            return Ok(false);
+            return Err(T3p0Error::WrongPlayerTurn);
         }
 
         if !self.compare_boards(game_state) {
-            return Ok(false);
+            return Err(T3p0Error::IllegalBoardChange);
+        }
+        // Otherwise player two could record an X, or player one an O, and win for the other side.
+        let mark = mark_for(game_state.message_number);
+        if (0..9).any(|i| self.board[i] != game_state.board[i] && game_state.board[i] != mark) {
+            return Err(T3p0Error::IllegalBoardChange);
         }
 
-        Ok(true)
+        // The first move of a game must place exactly one mark, no matter what the previous board held.
+        if game_state.turn == 1
+            && game_state
+                .board
+                .iter()
+                .filter(|&&square| square != 0)
+                .count()
+                != 1
+        {
+            return Err(T3p0Error::FirstMoveNotOneMark);
+        }
+
+        Ok(())
     }
 
     fn to_request(&self) -> Request {
         self.request
     }
+
+    /// Sets the mode the game is played in.
+    /// This is meant to be chosen when the game is created, before any turns are validated.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The mode to play the game in.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The same GameState in the new mode.
+    fn with_mode(mut self, mode: GameMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    fn get_mode(&self) -> GameMode {
+        self.mode
+    }
+
+    /// Sets who may watch the game.
+    /// Like the mode, this is meant to be chosen when the game is created.
+    ///
+    /// # Arguments
+    ///
+    /// * `privacy` - Who may watch.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The same GameState with the new privacy.
+    fn with_privacy(mut self, privacy: Privacy) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
+    fn get_privacy(&self) -> Privacy {
+        self.privacy
+    }
+
+    /// Sets who is playing, replacing the two players given to `new`.
+    /// Use this for team games.
+    ///
+    /// # Arguments
+    ///
+    /// * `roster` - The sides and how teammates take turns.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The same GameState with the new roster.
+    fn with_roster(mut self, roster: Roster) -> Self {
+        self.players = Some(Box::new(roster));
+        self
+    }
+
+    fn get_roster(&self) -> Option<&Roster> {
+        self.players.as_deref()
+    }
+
+    /// Returns true if observers may watch the game.
+    fn allows_spectators(&self) -> bool {
+        self.privacy != Privacy::Private
+    }
+
+    /// Checks whether the game is over.
+    ///
+    /// # Returns
+    ///
+    /// * `GameResult` - The winner, a draw if the board is full, or `InProgress`.
+    fn check_winner(&self) -> GameResult {
+        let marks = |mark: u8| {
+            self.board
+                .iter()
+                .enumerate()
+                .filter(|(_, &square)| square == mark)
+                .fold(0u16, |marks, (i, _)| marks | 1 << i)
+        };
+        result_of(marks(X_MARK), marks(O_MARK))
+    }
 }
 
 #[cfg(test)]
@@ -167,24 +406,27 @@ mod game_state_test {
 
     #[test]
     fn test_new() {
-        let gs = GameState::new(None, Some([Player::new(), Player::new()]));
+        let gs = GameState::new(
+            None,
+            Some([Player::from_bytes(&[11; 16]), Player::from_bytes(&[12; 16])]),
+        );
         assert_eq!(gs.board, [0u8; 9]);
         assert_eq!(gs.turn, 0);
         assert_eq!(gs.message_number, 0);
-        assert_eq!(gs.p2_turn, true);
+        assert!(gs.p2_turn);
     }
 
     #[test]
     fn test_from_request() {
         let r = Request::new_data_request(true);
-        let gs = GameState::from_request(r, Player::new());
+        let gs = GameState::from_request(r, Player::from_bytes(&[13; 16]));
         assert!(gs.is_ok());
 
         let gs = gs.unwrap();
         assert_eq!(gs.board, [0u8; 9]);
         assert_eq!(gs.turn, 0);
         assert_eq!(gs.message_number, 0);
-        assert_eq!(gs.p2_turn, false);
+        assert!(!gs.p2_turn);
     }
 
     #[test]
@@ -193,81 +435,86 @@ mod game_state_test {
         r = Request(
             r.0 ^ (1 << Bits::P2Turn as u32)
                 | (1 << Bits::MessageNumber as u32)
-                | (1 << Bits::TurnOffset as u32),
+                | (1 << Bits::TurnOffset as u32)
+                | 1,
         );
-        let gs = GameState::from_request(r, Player::new());
+        let gs = GameState::from_request(r, Player::from_bytes(&[14; 16]));
         assert!(gs.is_ok());
         let gs = gs.unwrap();
-        assert_eq!(gs.board, [0u8; 9]);
+        assert_eq!(gs.board, [1u8, 0, 0, 0, 0, 0, 0, 0, 0]);
         assert_eq!(gs.turn, 1);
         assert_eq!(gs.message_number, 1);
-        assert_eq!(gs.p2_turn, true);
+        assert!(gs.p2_turn);
     }
 
     #[test]
     fn test_from_request_board_all_ones() {
+        // A game can't start from a full board.
         let r = Request(0b111111111);
-        let gs = GameState::from_request(r, Player::new());
+        let gs = GameState::from_request(r, Player::from_bytes(&[15; 16]));
+        assert!(gs.is_err());
+
+        let r = Request(0b111111111).set_turn(8).set_message_number(8);
+        let gs = GameState::from_request(r, Player::from_bytes(&[16; 16]));
         assert!(gs.is_ok());
         let gs = gs.unwrap();
         assert_eq!(gs.board, [1u8; 9]);
-        assert_eq!(gs.turn, 0);
-        assert_eq!(gs.message_number, 0);
-        assert_eq!(gs.p2_turn, false);
+        assert_eq!(gs.turn, 8);
+        assert_eq!(gs.message_number, 8);
+        assert!(!gs.p2_turn);
     }
 
     #[test]
     fn test_from_request_invalid_turn() {
         let r = Request((1 << Bits::TurnOffset as u32) | (1 << Bits::MessageNumber as u32));
-        let gs = GameState::from_request(r, Player::new());
+        let gs = GameState::from_request(r, Player::from_bytes(&[17; 16]));
         assert!(gs.is_err());
     }
     #[test]
     fn test_from_request_invalid_player() {
         let r = Request(1 << Bits::P2Turn as u32);
-        let gs = GameState::from_request(r, Player::new());
+        let gs = GameState::from_request(r, Player::from_bytes(&[18; 16]));
         assert!(gs.is_err());
     }
 
     #[test]
     fn test_compare_boards() {
-        let players = [Player::new(), Player::new()];
+        let players = [Player::from_bytes(&[19; 16]), Player::from_bytes(&[20; 16])];
         let mut gs = GameState::new(None, Some(players.clone()));
         let mut gs2 = GameState::new(None, Some(players.clone()));
         // This is false because no changes have been made, you can't pass your turn in tic tac toe
-        assert_eq!(gs.compare_boards(&gs2), false);
+        assert!(!gs.compare_boards(&gs2));
         gs2.board[0] = 1;
-        assert_eq!(gs.compare_boards(&gs2), true);
+        assert!(gs.compare_boards(&gs2));
         gs.board[0] = 1;
         gs2.board[0] = 2;
-        assert_eq!(gs.compare_boards(&gs2), false);
+        assert!(!gs.compare_boards(&gs2));
     }
 
     // COPILOT GENERATED THESE TESTS
     // VALIDATE THEY ARE CORRECT
     #[test]
     fn test_valid_turn() {
-        let players = [Player::new(), Player::new()];
+        let players = [Player::from_bytes(&[21; 16]), Player::from_bytes(&[22; 16])];
         let mut gs = GameState::new(None, Some(players.clone()));
         gs.turn = 0;
         gs.message_number = 0;
         gs.p2_turn = false;
-        gs.submitted_by = players[0].clone();
+        gs.submitted_by = players[1].clone();
 
         let mut gs2 = GameState::new(None, Some(players.clone()));
         gs2.turn = 1;
         gs2.message_number = 1;
         gs2.p2_turn = true;
-        gs2.submitted_by = players[1].clone();
+        gs2.submitted_by = players[0].clone();
         gs2.board = [1u8, 0, 0, 0, 0, 0, 0, 0, 0];
 
-        assert!(gs.validate_turn(&gs2).is_ok());
-        assert_eq!(gs.validate_turn(&gs2).unwrap(), true);
+        assert_eq!(gs.validate_turn(&gs2), Ok(()));
     }
 
     #[test]
     fn test_invalid_turn_number() {
-        let players = [Player::new(), Player::new()];
+        let players = [Player::from_bytes(&[23; 16]), Player::from_bytes(&[24; 16])];
         let mut gs = GameState::new(None, Some(players.clone()));
         gs.turn = 2;
         gs.message_number = 1;
@@ -280,12 +527,30 @@ mod game_state_test {
         gs2.p2_turn = true;
         gs2.submitted_by = players[1].clone();
 
-        assert_eq!(gs.validate_turn(&gs2).unwrap(), false);
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::NotNextMove));
+    }
+
+    #[test]
+    fn test_counters_at_maximum() {
+        let players = [Player::from_bytes(&[25; 16]), Player::from_bytes(&[26; 16])];
+        let mut gs = GameState::new(None, Some(players.clone()));
+        gs.turn = u8::MAX;
+        gs.message_number = u8::MAX;
+        gs.p2_turn = false;
+        gs.submitted_by = players[0].clone();
+
+        let mut gs2 = GameState::new(None, Some(players.clone()));
+        gs2.turn = 0;
+        gs2.message_number = 0;
+        gs2.p2_turn = true;
+        gs2.submitted_by = players[1].clone();
+
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::NotNextMove));
     }
 
     #[test]
     fn test_invalid_message_number() {
-        let players = [Player::new(), Player::new()];
+        let players = [Player::from_bytes(&[27; 16]), Player::from_bytes(&[28; 16])];
         let mut gs = GameState::new(None, Some(players.clone()));
         gs.turn = 1;
         gs.message_number = 2;
@@ -298,12 +563,12 @@ mod game_state_test {
         gs2.p2_turn = true;
         gs2.submitted_by = players[1].clone();
 
-        assert_eq!(gs.validate_turn(&gs2).unwrap(), false);
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::NotNextMove));
     }
 
     #[test]
     fn test_invalid_same_player_turn() {
-        let players = [Player::new(), Player::new()];
+        let players = [Player::from_bytes(&[29; 16]), Player::from_bytes(&[30; 16])];
         let mut gs = GameState::new(None, Some(players.clone()));
         gs.turn = 1;
         gs.message_number = 1;
@@ -316,17 +581,17 @@ mod game_state_test {
         gs2.p2_turn = true;
         gs2.submitted_by = players[0].clone();
 
-        assert_eq!(gs.validate_turn(&gs2).unwrap(), false);
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::NotNextMove));
     }
 
     #[test]
     fn test_invalid_submitted_by_not_player() {
-        let players = [Player::new(), Player::new()];
+        let players = [Player::from_bytes(&[31; 16]), Player::from_bytes(&[32; 16])];
         let mut gs = GameState::new(None, Some(players.clone()));
         gs.turn = 1;
         gs.message_number = 1;
         gs.p2_turn = false;
-        gs.submitted_by = Player::new();
+        gs.submitted_by = Player::from_bytes(&[33; 16]);
 
         let mut gs2 = GameState::new(None, Some(players.clone()));
         gs2.turn = 0;
@@ -334,6 +599,184 @@ mod game_state_test {
         gs2.p2_turn = true;
         gs2.submitted_by = players[0].clone();
 
-        assert_eq!(gs.validate_turn(&gs2).unwrap(), false);
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::NotNextMove));
+    }
+
+    #[test]
+    fn test_result_of() {
+        assert_eq!(result_of(0, 0), GameResult::InProgress);
+        assert_eq!(result_of(0b000_000_111, 0b000_011_000), GameResult::XWins);
+        assert_eq!(result_of(0b000_011_001, 0b100_100_100), GameResult::OWins);
+        assert_eq!(result_of(0b001_010_100, 0), GameResult::XWins);
+        // X O X / X O O / O X X
+        assert_eq!(result_of(0b110_001_101, 0b001_110_010), GameResult::Draw);
+        // A full board with a line is a win, not a draw.
+        assert_eq!(result_of(0b111_001_101, 0b000_110_010), GameResult::XWins);
+    }
+
+    #[test]
+    fn test_from_request_marks() {
+        let r = Request::new_data_request(false)
+            .set_turn(3)
+            .set_message_number(3)
+            .set_p2_turn(true)
+            .set_board(0b000_010_011)
+            .set_o_marks(0b000_010_000);
+        let gs = GameState::from_request(r, Player::from_bytes(&[34; 16])).unwrap();
+        assert_eq!(gs.board, [X_MARK, X_MARK, 0, 0, O_MARK, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_check_winner() {
+        let mut gs = GameState::new(None, None);
+        assert_eq!(gs.check_winner(), GameResult::InProgress);
+        gs.board = [
+            O_MARK, X_MARK, 0, //
+            O_MARK, X_MARK, 0, //
+            O_MARK, 0, X_MARK,
+        ];
+        assert_eq!(gs.check_winner(), GameResult::OWins);
+        gs.board[6] = X_MARK;
+        assert_eq!(gs.check_winner(), GameResult::InProgress);
+    }
+
+    #[test]
+    fn test_team_rotation() {
+        use crate::roster::Rotation;
+
+        let team_one = vec![Player::from_bytes(&[35; 16]), Player::from_bytes(&[36; 16])];
+        let team_two = vec![Player::from_bytes(&[37; 16]), Player::from_bytes(&[38; 16])];
+        let roster =
+            Roster::teams(team_one.clone(), team_two.clone(), Rotation::Alternate).unwrap();
+        let mut gs = GameState::new(None, None).with_roster(roster);
+        gs.turn = 2;
+        gs.message_number = 2;
+        gs.p2_turn = false;
+        gs.submitted_by = team_two[0].clone();
+        gs.board = [1u8, 2, 0, 0, 0, 0, 0, 0, 0];
+
+        let mut gs2 = GameState::new(None, None);
+        gs2.turn = 3;
+        gs2.message_number = 3;
+        gs2.p2_turn = true;
+        gs2.board = [1u8, 2, 1, 0, 0, 0, 0, 0, 0];
+
+        // Message 3 is the first side's second move, so it belongs to the second member.
+        gs2.submitted_by = team_one[1].clone();
+        assert_eq!(gs.validate_turn(&gs2), Ok(()));
+        gs2.submitted_by = team_one[0].clone();
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::WrongPlayerTurn));
+        gs2.submitted_by = team_two[1].clone();
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::WrongPlayerTurn));
+    }
+
+    #[test]
+    fn test_wrong_mark_from_each_side() {
+        let (x, o) = (Player::from_bytes(&[1; 16]), Player::from_bytes(&[2; 16]));
+        let start = GameState::from_request(Request::new_data_request(false), o.clone())
+            .unwrap()
+            .with_roster(Roster::pair(x.clone(), o.clone()));
+        let first_move = Request::new_data_request(false)
+            .increment_turn_and_message()
+            .unwrap()
+            .set_board(1 << 4);
+
+        // The first player places an O.
+        let as_o = GameState::from_request(first_move.set_o_marks(1 << 4), x.clone()).unwrap();
+        assert_eq!(
+            start.validate_turn(&as_o),
+            Err(T3p0Error::IllegalBoardChange)
+        );
+        let after_x = GameState::from_request(first_move, x.clone()).unwrap();
+        assert_eq!(start.validate_turn(&after_x), Ok(()));
+
+        // The second player leaves the O bit unset, recording an X.
+        let second_move = first_move
+            .increment_turn_and_message()
+            .unwrap()
+            .set_board(1 << 4 | 1);
+        let as_x = GameState::from_request(second_move, o.clone()).unwrap();
+        assert_eq!(
+            after_x.validate_turn(&as_x),
+            Err(T3p0Error::IllegalBoardChange)
+        );
+        let as_o = GameState::from_request(second_move.set_o_marks(1), o).unwrap();
+        assert_eq!(after_x.validate_turn(&as_o), Ok(()));
+    }
+
+    #[test]
+    fn test_privacy_names() {
+        for privacy in [Privacy::Public, Privacy::Unlisted, Privacy::Private] {
+            assert_eq!(Privacy::from_name(privacy.name()), Ok(privacy));
+        }
+        assert!(Privacy::from_name("secret").is_err());
+        assert_eq!(Privacy::Public.max(Privacy::Private), Privacy::Private);
+    }
+
+    #[test]
+    fn test_privacy() {
+        let gs = GameState::new(None, None);
+        assert_eq!(gs.get_privacy(), Privacy::Public);
+        assert!(gs.allows_spectators());
+        let gs = gs.with_privacy(Privacy::Unlisted);
+        assert_eq!(gs.get_privacy(), Privacy::Unlisted);
+        assert!(gs.allows_spectators());
+        assert!(!gs.with_privacy(Privacy::Private).allows_spectators());
+    }
+
+    #[test]
+    fn test_sandbox_same_player_turn() {
+        let player = Player::from_bytes(&[39; 16]);
+        let mut gs = GameState::new(Some(player.clone()), Some([player.clone(), player.clone()]))
+            .with_mode(GameMode::Sandbox);
+        gs.p2_turn = false;
+
+        let mut gs2 = GameState::new(Some(player.clone()), None);
+        gs2.turn = 1;
+        gs2.message_number = 1;
+        gs2.p2_turn = true;
+        gs2.board = [1u8, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(gs.get_mode(), GameMode::Sandbox);
+        assert_eq!(gs.validate_turn(&gs2), Ok(()));
+        // The same move is rejected outside of sandbox mode.
+        assert_eq!(
+            gs.with_mode(GameMode::Standard).validate_turn(&gs2),
+            Err(T3p0Error::WrongPlayerTurn)
+        );
+    }
+
+    #[test]
+    fn test_sandbox_still_checks_board() {
+        let player = Player::from_bytes(&[40; 16]);
+        let mut gs = GameState::new(Some(player.clone()), None).with_mode(GameMode::Sandbox);
+        gs.p2_turn = false;
+        gs.board = [1u8, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let mut gs2 = GameState::new(Some(player.clone()), None);
+        gs2.turn = 1;
+        gs2.message_number = 1;
+        gs2.p2_turn = true;
+        gs2.board = [2u8, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::IllegalBoardChange));
+    }
+
+    #[test]
+    fn test_first_move_over_prefilled_board() {
+        let players = [Player::from_bytes(&[41; 16]), Player::from_bytes(&[42; 16])];
+        let mut gs = GameState::new(None, Some(players.clone()));
+        gs.p2_turn = false;
+        gs.submitted_by = players[1].clone();
+        gs.board = [1u8, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let mut gs2 = GameState::new(None, Some(players.clone()));
+        gs2.turn = 1;
+        gs2.message_number = 1;
+        gs2.p2_turn = true;
+        gs2.submitted_by = players[0].clone();
+        gs2.board = [1u8, 1, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(gs.validate_turn(&gs2), Err(T3p0Error::FirstMoveNotOneMark));
     }
 }
@@ -0,0 +1,174 @@
+// A lock-free histogram for latencies, in the spirit of HDR histograms.
+// Values are grouped by their most significant bit and then split into 8 linear sub buckets,
+// so every recorded value is kept to within 12.5% of its real value no matter how large it is.
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Number of bits below the most significant bit used to pick a sub bucket.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Enough buckets to hold any u64.
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    max: AtomicU64,
+}
+
+/// Percentiles read from a `Histogram`, in microseconds.
+/// Each percentile is the upper bound of the bucket it falls in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+pub trait HistogramTrait {
+    fn new() -> Self;
+    fn record(&self, elapsed: Duration);
+    fn percentile(&self, percentile: f64) -> u64;
+    fn snapshot(&self) -> HistogramSnapshot;
+}
+
+/// Finds the bucket a value belongs in.
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let msb = 63 - value.leading_zeros();
+    let shift = msb - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) as usize & (SUB_BUCKETS - 1);
+    (shift as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+/// The largest value that lands in a bucket.
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let sub_bucket = (index % SUB_BUCKETS) as u64;
+    let lower = (SUB_BUCKETS as u64 + sub_bucket) << shift;
+    lower + ((1u64 << shift) - 1)
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl HistogramTrait for Histogram {
+    fn new() -> Self {
+        Histogram::default()
+    }
+
+    /// Records a duration with microsecond resolution.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - The duration to record.
+    fn record(&self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(micros)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Gets the value that the given percentage of recorded values are less than or equal to.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - A percentage between 0 and 100.
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The percentile in microseconds, or 0 if nothing has been recorded.
+    fn percentile(&self, percentile: f64) -> u64 {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return 0;
+        }
+        let target = ((percentile.clamp(0.0, 100.0) / 100.0 * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                return bucket_upper_bound(index).min(self.max.load(Ordering::Relaxed));
+            }
+        }
+        self.max.load(Ordering::Relaxed)
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            p50_us: self.percentile(50.0),
+            p90_us: self.percentile(90.0),
+            p99_us: self.percentile(99.0),
+            max_us: self.max.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_index_small_values_are_exact() {
+        for value in 0..SUB_BUCKETS as u64 {
+            assert_eq!(bucket_index(value), value as usize);
+            assert_eq!(bucket_upper_bound(value as usize), value);
+        }
+    }
+
+    #[test]
+    fn bucket_bounds_contain_value() {
+        for value in [8, 9, 15, 16, 17, 100, 1000, 123_456, u64::MAX / 3, u64::MAX] {
+            let index = bucket_index(value);
+            assert!(index < BUCKETS);
+            assert!(bucket_upper_bound(index) >= value);
+            // Within one sub bucket, i.e. 12.5%.
+            assert!(bucket_upper_bound(index) - value <= value / SUB_BUCKETS as u64);
+        }
+    }
+
+    #[test]
+    fn empty_snapshot() {
+        let histogram = Histogram::new();
+        assert_eq!(histogram.snapshot(), HistogramSnapshot::default());
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = Histogram::new();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 100);
+        assert_eq!(snapshot.max_us, 100);
+        assert!((50..=56).contains(&snapshot.p50_us));
+        assert!((90..=100).contains(&snapshot.p90_us));
+        assert!((99..=100).contains(&snapshot.p99_us));
+    }
+
+    #[test]
+    fn percentile_never_exceeds_max() {
+        let histogram = Histogram::new();
+        histogram.record(Duration::from_micros(1000));
+        assert_eq!(histogram.percentile(100.0), 1000);
+    }
+}
@@ -0,0 +1,115 @@
+// Keep-alive settings for long lived connections.
+// Correspondence games can sit idle for a long time and NAT devices drop mappings that see no
+// traffic, so the server uses both OS level TCP keepalive probes and application level pings.
+// The presets also bound how long a single socket operation may take, so a peer that stops
+// responding mid-operation can't hold a task forever.
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAliveConfig {
+    /// How long a connection is idle before the OS sends the first TCP keepalive probe.
+    pub tcp_idle: Duration,
+    /// Time between TCP keepalive probes once they have started.
+    pub tcp_interval: Duration,
+    /// Number of unanswered TCP keepalive probes before the OS drops the connection.
+    pub tcp_retries: u32,
+    /// How long a connection is idle before the server sends an Ok response as a ping.
+    pub ping_interval: Duration,
+    /// How long the client has to finish the handshake.
+    pub handshake_timeout: Duration,
+    /// How long a single write may wait on a client that isn't reading.
+    pub write_timeout: Duration,
+}
+
+pub trait KeepAliveConfigTrait {
+    fn lan() -> Self;
+    fn mobile() -> Self;
+    fn from_preset(name: &str) -> Result<Self, &'static str>
+    where
+        Self: Sized;
+}
+
+impl KeepAliveConfigTrait for KeepAliveConfig {
+    /// Settings for clients on a local network. Dead peers are noticed quickly and there is
+    /// no NAT to keep open, so pings are rare.
+    fn lan() -> Self {
+        KeepAliveConfig {
+            tcp_idle: Duration::from_secs(30),
+            tcp_interval: Duration::from_secs(5),
+            tcp_retries: 3,
+            ping_interval: Duration::from_secs(300),
+            handshake_timeout: Duration::from_secs(5),
+            write_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Settings for clients behind carrier NAT, which commonly drops idle mappings after a
+    /// few minutes. Traffic is sent often enough to keep the mapping open without draining
+    /// batteries, and peers are given longer to come back from a network switch.
+    fn mobile() -> Self {
+        KeepAliveConfig {
+            tcp_idle: Duration::from_secs(60),
+            tcp_interval: Duration::from_secs(15),
+            tcp_retries: 8,
+            ping_interval: Duration::from_secs(45),
+            handshake_timeout: Duration::from_secs(20),
+            write_timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Looks up a preset by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Either `lan` or `mobile`.
+    ///
+    /// # Errors
+    ///
+    /// * `&'static str` - If the name isn't a known preset.
+    fn from_preset(name: &str) -> Result<Self, &'static str> {
+        match name {
+            "lan" => Ok(KeepAliveConfig::lan()),
+            "mobile" => Ok(KeepAliveConfig::mobile()),
+            _ => Err("Unknown keep-alive preset, expected lan or mobile."),
+        }
+    }
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        KeepAliveConfig::lan()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_preset() {
+        assert_eq!(
+            KeepAliveConfig::from_preset("lan"),
+            Ok(KeepAliveConfig::lan())
+        );
+        assert_eq!(
+            KeepAliveConfig::from_preset("mobile"),
+            Ok(KeepAliveConfig::mobile())
+        );
+        assert!(KeepAliveConfig::from_preset("satellite").is_err());
+    }
+
+    #[test]
+    fn mobile_pings_before_common_nat_timeouts() {
+        // Some carrier NATs drop idle mappings after as little as 60 seconds.
+        assert!(KeepAliveConfig::mobile().ping_interval < Duration::from_secs(60));
+        assert!(KeepAliveConfig::mobile().ping_interval < KeepAliveConfig::lan().ping_interval);
+    }
+
+    #[test]
+    fn writes_give_up_before_the_peer_is_pinged_again() {
+        for config in [KeepAliveConfig::lan(), KeepAliveConfig::mobile()] {
+            assert!(config.write_timeout < config.ping_interval);
+            assert!(config.handshake_timeout < config.ping_interval);
+        }
+    }
+}
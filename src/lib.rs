@@ -1,7 +1,59 @@
+pub mod client;
+pub mod connection;
+pub mod discovery;
+pub mod error;
+pub mod event_log;
+pub mod frame_type;
 pub mod game_state;
+pub mod histogram;
+pub mod keep_alive;
+#[cfg(feature = "server")]
+pub mod mailbox;
+pub mod match_options;
+pub mod matchmaking;
+pub mod nack;
+pub mod observer;
 pub mod player;
+pub mod quarantine;
+pub mod rate_limit;
 pub mod request;
+pub mod roster;
+pub mod runtime_layout;
+pub mod send_queue;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session_file;
+pub mod stats;
+#[cfg(feature = "status-page")]
+pub mod status_page;
+pub mod wire_format;
 
-pub use game_state::{GameState, GameStateTrait};
+#[cfg(feature = "async-client")]
+pub use client::Client;
+pub use client::{BoardView, ClientEvent, UserBot};
+pub use connection::{Action, ConnectionCore, ConnectionCoreTrait};
+pub use discovery::{discover_servers, Announcement, AnnouncementTrait};
+pub use error::T3p0Error;
+pub use event_log::{EventLog, EventLogTrait, LogEvent, LogEventKind};
+pub use frame_type::FrameType;
+pub use game_state::{GameMode, GameResult, GameState, GameStateTrait, Privacy};
+pub use histogram::{Histogram, HistogramSnapshot, HistogramTrait};
+pub use keep_alive::{KeepAliveConfig, KeepAliveConfigTrait};
+#[cfg(feature = "server")]
+pub use mailbox::{mailbox, Lane, Mailbox, MailboxSender};
+pub use match_options::{MatchOptions, MatchOptionsTrait};
+pub use matchmaking::{new_match, Matchmaker, MatchmakerTrait};
+pub use nack::{Nack, NackCode, NackTrait};
 pub use player::{Player, PlayerTrait};
+pub use quarantine::{Quarantine, QuarantineRecord, QuarantineRecordTrait, QuarantineTrait};
+pub use rate_limit::{TokenBucket, TokenBucketTrait};
 pub use request::DataRequest;
+pub use roster::{Roster, RosterTrait, Rotation};
+pub use runtime_layout::RuntimeLayout;
+pub use send_queue::{FrameClass, OverflowPolicy, SendQueue, SendQueueTrait};
+#[cfg(feature = "server")]
+pub use server::{Server, ServerConfig};
+pub use session_file::{ClientSession, ClientSessionTrait};
+pub use stats::{RecentResult, Stats, StatsSnapshot, StatsTrait};
+#[cfg(feature = "status-page")]
+pub use status_page::{StatusReport, StatusReportTrait};
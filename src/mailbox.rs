@@ -0,0 +1,144 @@
+// A mailbox with two priority lanes for the state actor.
+// Moves go in the high lane and everything else (observers, the status page) in the low lane.
+// The actor always empties the high lane first, so a burst of low priority traffic can delay
+// a move by at most the one low priority message already being handled.
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    High,
+    Low,
+}
+
+#[derive(Debug)]
+pub struct MailboxSender<T> {
+    high: mpsc::Sender<T>,
+    low: mpsc::Sender<T>,
+}
+
+// Derived Clone would needlessly require `T: Clone`.
+impl<T> Clone for MailboxSender<T> {
+    fn clone(&self) -> Self {
+        MailboxSender {
+            high: self.high.clone(),
+            low: self.low.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mailbox<T> {
+    high: mpsc::Receiver<T>,
+    low: mpsc::Receiver<T>,
+}
+
+/// Creates a mailbox where each lane holds up to `capacity` messages.
+pub fn mailbox<T>(capacity: usize) -> (MailboxSender<T>, Mailbox<T>) {
+    let (high_tx, high_rx) = mpsc::channel(capacity);
+    let (low_tx, low_rx) = mpsc::channel(capacity);
+    (
+        MailboxSender {
+            high: high_tx,
+            low: low_tx,
+        },
+        Mailbox {
+            high: high_rx,
+            low: low_rx,
+        },
+    )
+}
+
+impl<T> MailboxSender<T> {
+    /// Sends a message on a lane, waiting while that lane is full.
+    ///
+    /// # Errors
+    ///
+    /// * `mpsc::error::SendError<T>` - If the mailbox has been dropped.
+    pub async fn send(&self, lane: Lane, message: T) -> Result<(), mpsc::error::SendError<T>> {
+        match lane {
+            Lane::High => self.high.send(message).await,
+            Lane::Low => self.low.send(message).await,
+        }
+    }
+}
+
+impl<T> Mailbox<T> {
+    /// Receives the next message, always taking from the high lane first.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<T>` - The message, or `None` once every sender is gone and both lanes are empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        tokio::select! {
+            biased;
+            Some(message) = self.high.recv() => Some(message),
+            Some(message) = self.low.recv() => Some(message),
+            else => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn high_lane_goes_first() {
+        let (tx, mut rx) = mailbox(4);
+        tx.send(Lane::Low, 1).await.unwrap();
+        tx.send(Lane::Low, 2).await.unwrap();
+        tx.send(Lane::High, 3).await.unwrap();
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn closes_after_draining() {
+        let (tx, mut rx) = mailbox(2);
+        tx.send(Lane::Low, 1).await.unwrap();
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn saturated_low_lane_does_not_delay_moves() {
+        const HANDLING_TIME: Duration = Duration::from_millis(1);
+        let (tx, mut rx) = mailbox::<Option<Instant>>(64);
+
+        // Keep the low lane full for the whole test.
+        let flood = tx.clone();
+        let flooder =
+            tokio::spawn(async move { while flood.send(Lane::Low, None).await.is_ok() {} });
+
+        let actor = tokio::spawn(async move {
+            let mut worst = Duration::ZERO;
+            let mut moves = 0;
+            while moves < 20 {
+                match rx.recv().await {
+                    Some(Some(sent)) => {
+                        worst = worst.max(sent.elapsed());
+                        moves += 1;
+                    }
+                    Some(None) => {}
+                    None => break,
+                }
+                std::thread::sleep(HANDLING_TIME);
+            }
+            worst
+        });
+
+        for _ in 0..20 {
+            tx.send(Lane::High, Some(Instant::now())).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let worst = actor.await.unwrap();
+        flooder.abort();
+
+        // Behind a full low lane a FIFO mailbox would take 64 handling times. With lanes a move
+        // waits for at most the message already being handled.
+        assert!(worst < HANDLING_TIME * 16, "worst move latency {:?}", worst);
+    }
+}
@@ -1,139 +1,68 @@
-use std::{collections::HashMap, sync::Arc};
-use t3p0::{request::Request, DataRequest, GameState, GameStateTrait, Player, PlayerTrait};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
-    sync::{mpsc, Mutex},
+use std::path::PathBuf;
+use t3p0::{
+    keep_alive::{KeepAliveConfig, KeepAliveConfigTrait},
+    runtime_layout::RuntimeLayout,
+    send_queue::OverflowPolicy,
+    server::{Server, ServerConfig},
 };
 
-#[derive(Debug)]
-enum GameRequest {
-    GetState {
-        player_id: Player,
-        response: mpsc::Sender<Option<GameState>>,
-    },
-    UpdateState {
-        player_id: Player,
-        new_state: GameState,
-    },
-}
+/// Setting this environment variable to a file path writes every rejected frame to a quarantine log there.
+const QUARANTINE_LOG_ENV: &str = "T3P0_QUARANTINE_LOG";
+/// Setting this environment variable to an address like `127.0.0.1:8001` opens the observer port there.
+const OBSERVER_ADDR_ENV: &str = "T3P0_OBSERVER_ADDR";
+/// Setting this environment variable answers LAN discovery queries, announcing the server under this name.
+const DISCOVERY_NAME_ENV: &str = "T3P0_DISCOVERY_NAME";
+/// Selects the keep-alive preset, `lan` (the default) or `mobile`.
+const KEEP_ALIVE_PRESET_ENV: &str = "T3P0_KEEPALIVE_PRESET";
+/// Selects what happens when a client's send queue is full, `drop-oldest-status` (the default) or `disconnect`.
+const SEND_QUEUE_OVERFLOW_ENV: &str = "T3P0_SEND_QUEUE_OVERFLOW";
+/// Selects how connections are scheduled, `multi-thread` (the default) or `thread-per-core`.
+const RUNTIME_LAYOUT_ENV: &str = "T3P0_RUNTIME_LAYOUT";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let listener = TcpListener::bind("127.0.0.1:8000").await?;
-    let (tx, mut rx) = mpsc::channel::<GameRequest>(32);
-    let game_state_map = Arc::new(Mutex::new(HashMap::<Player, GameState>::new()));
-
-    let game_state_map_clone = game_state_map.clone();
-    tokio::spawn(async move {
-        while let Some(request) = rx.recv().await {
-            let mut state = game_state_map_clone.lock().await;
-            match request {
-                GameRequest::GetState {
-                    player_id,
-                    response,
-                } => {
-                    let game_state = state.get(&player_id).cloned();
-                    let _ = response.send(game_state);
-                }
-                GameRequest::UpdateState {
-                    player_id,
-                    new_state,
-                } => {
-                    state.insert(player_id, new_state);
-                }
-            }
-        }
-    });
+    let mut config = ServerConfig {
+        quarantine_log: std::env::var_os(QUARANTINE_LOG_ENV).map(PathBuf::from),
+        discovery_name: std::env::var(DISCOVERY_NAME_ENV).ok(),
+        ..ServerConfig::default()
+    };
+    if let Ok(addr) = std::env::var(OBSERVER_ADDR_ENV) {
+        config.observer_addr = Some(addr.parse()?);
+    }
+    if let Ok(preset) = std::env::var(KEEP_ALIVE_PRESET_ENV) {
+        config.keep_alive = KeepAliveConfig::from_preset(&preset)?;
+    }
+    if let Ok(name) = std::env::var(SEND_QUEUE_OVERFLOW_ENV) {
+        config.overflow_policy = OverflowPolicy::from_name(&name)?;
+    }
+    if let Ok(name) = std::env::var(RUNTIME_LAYOUT_ENV) {
+        config.runtime_layout = RuntimeLayout::from_name(&name)?;
+    }
 
-    loop {
-        let (socket, _) = listener.accept().await?;
-        let tx_clone = tx.clone();
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(socket, tx_clone).await {
-                eprintln!("Error: {:?}", e);
-            }
-        });
+    let server = Server::bind(config).await?;
+    let run = server.run();
+    tokio::pin!(run);
+    tokio::select! {
+        result = &mut run => return Ok(result?),
+        _ = shutdown_signal() => server.shutdown(),
     }
+    Ok(run.await?)
 }
 
-async fn handle_connection(
-    mut socket: TcpStream,
-    tx: mpsc::Sender<GameRequest>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut buffer = [0u8; 4];
-    let mut player = Player::new();
-    println!("New connection: {}", socket.peer_addr()?);
-    println!("Player: {:?}", player);
-    // Handshake
-    for i in 0..2 {
-        let n = socket.read(&mut buffer).await?;
-        if n == 0 {
-            return Err("Connection closed".into());
-        }
-
-        // Client should first send hello (or ok) message
-        // The server will assign a player number to the client.
-        // The user should then send another ok message
-        // If the player instead responds with a player id, the server will assign the player number to the client.
-        match n {
-            4 => {
-                let request = Request(u32::from_be_bytes(buffer));
-                if i == 0 && request.is_ok_response() {
-                    socket.write(&player.get_id().to_bytes_le()).await?;
-                }
-            }
-            16 => {
-                if i == 0 {
-                    return Err("Invalid handshake message".into());
-                }
-                let mut uuid_buffer = [0u8; 16];
-                uuid_buffer[..4].copy_from_slice(&buffer);
-                socket.read_exact(&mut uuid_buffer[4..]).await?;
-                player = Player::from_bytes(&uuid_buffer);
-                socket
-                    .write(&Request::new_data_request(true).0.to_be_bytes())
-                    .await?;
-            }
-            _ => {
-                return Err("Invalid handshake message".into());
-            }
+/// Resolves when the process is asked to stop, either by Ctrl+C or (on unix) SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
         }
     }
-
-    // Event loop
-    loop {
-        let n = socket.read(&mut buffer).await?;
-        if n == 0 {
-            break;
-        }
-        if n != 4 {
-            return Err("Invalid request".into());
-        }
-
-        let request = Request(u32::from_be_bytes(buffer));
-        // If the request is not a valid request, we break the loop
-        // If it is an ok request send an ok request back.
-        // If the user doesn't receive the ok request, they will close the connection and try again.
-
-        let (response_tx, mut response_rx) = mpsc::channel::<Option<GameState>>(1);
-        tx.send(GameRequest::GetState {
-            player_id: player.clone(),
-            response: response_tx,
-        })
-        .await?;
-
-        if let Some(game_state_rec) = response_rx.recv().await {
-            if let Some(game_state) = game_state_rec {
-                socket
-                    .write(&game_state.to_request().0.to_be_bytes())
-                    .await?;
-            } else {
-                socket
-                    .write(&request.0.to_be_bytes())
-                    .await?;
-            }
-        }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
     }
-    Ok(())
 }
@@ -0,0 +1,134 @@
+// What a player asks for when they join a match.
+// The options are sent as the last frame of the handshake, in place of the Ok that accepts the
+// assigned id. They are an Ok response with option bits set below the message type, so a client
+// that doesn't know about options sends a plain Ok and gets the defaults.
+
+/// |----|--------------|
+/// | 1  | Message Type | Always set, like an Ok response.
+/// |----|--------------|
+/// | 2  | Unused       |
+/// | .. |              |
+/// | 29 |              |
+/// |----|--------------|
+/// | 30 | Privacy      | 0 public, 1 unlisted, 2 private. When two players are matched the
+/// | 31 |              | game gets the stricter of their settings.
+/// |----|--------------|
+/// | 32 | Sandbox      | Play both sides on this connection instead of waiting for an opponent.
+/// |----|--------------|
+use crate::{
+    game_state::{GameMode, Privacy},
+    request::{Bits, DataRequest, Request},
+};
+
+/// Offset of the bit that asks for a sandbox game.
+pub(crate) const SANDBOX_OFFSET: u32 = 0;
+/// Offset of the privacy setting, right after the sandbox bit.
+pub(crate) const PRIVACY_OFFSET: u32 = SANDBOX_OFFSET + 1;
+/// Number of bits reserved for the privacy setting.
+pub(crate) const PRIVACY_RANGE: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchOptions {
+    /// `GameMode::Sandbox` starts a game straight away with this connection playing both sides.
+    pub mode: GameMode,
+    /// Who may watch the game.
+    pub privacy: Privacy,
+}
+
+pub trait MatchOptionsTrait {
+    fn to_request(&self) -> Request;
+    fn from_request(request: Request) -> Self;
+}
+
+impl MatchOptionsTrait for MatchOptions {
+    /// Encodes the options as the last frame of the handshake.
+    ///
+    /// # Returns
+    ///
+    /// * `Request` - An Ok response with the option bits set, or a plain Ok for the defaults.
+    fn to_request(&self) -> Request {
+        let sandbox = u32::from(self.mode == GameMode::Sandbox) << SANDBOX_OFFSET;
+        let privacy = match self.privacy {
+            Privacy::Public => 0,
+            Privacy::Unlisted => 1,
+            Privacy::Private => 2,
+        } << PRIVACY_OFFSET;
+        Request(Request::new_data_request(true).0 | sandbox | privacy)
+    }
+
+    /// Decodes the last frame of the handshake. Anything that isn't an Ok frame with option bits
+    /// asks for the defaults, since older clients were free to send any frame there. An unknown
+    /// privacy value is read as private so a client never gets less privacy than it asked for.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The frame the client sent.
+    ///
+    /// # Returns
+    ///
+    /// * `MatchOptions` - The options the client asked for.
+    fn from_request(request: Request) -> Self {
+        if request.0 >> Bits::MessageType as u32 == 0 {
+            return MatchOptions::default();
+        }
+        let mode = if request.0 >> SANDBOX_OFFSET & 1 == 1 {
+            GameMode::Sandbox
+        } else {
+            GameMode::Standard
+        };
+        let privacy = match request.0 >> PRIVACY_OFFSET & ((1 << PRIVACY_RANGE) - 1) {
+            0 => Privacy::Public,
+            1 => Privacy::Unlisted,
+            _ => Privacy::Private,
+        };
+        MatchOptions { mode, privacy }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_a_plain_ok() {
+        let ok = Request::new_data_request(true);
+        assert_eq!(MatchOptions::default().to_request(), ok);
+        assert_eq!(MatchOptions::from_request(ok), MatchOptions::default());
+    }
+
+    #[test]
+    fn sandbox_round_trips() {
+        let sandbox = MatchOptions {
+            mode: GameMode::Sandbox,
+            ..MatchOptions::default()
+        };
+        let request = sandbox.to_request();
+        assert!(!request.is_ok_response());
+        assert_eq!(MatchOptions::from_request(request), sandbox);
+    }
+
+    #[test]
+    fn privacy_round_trips() {
+        for privacy in [Privacy::Public, Privacy::Unlisted, Privacy::Private] {
+            let options = MatchOptions {
+                privacy,
+                ..MatchOptions::default()
+            };
+            assert_eq!(MatchOptions::from_request(options.to_request()), options);
+        }
+        let unknown = Request(Request::new_data_request(true).0 | 3 << PRIVACY_OFFSET);
+        assert_eq!(
+            MatchOptions::from_request(unknown).privacy,
+            Privacy::Private
+        );
+    }
+
+    #[test]
+    fn data_frame_asks_for_defaults() {
+        let first_move = Request(0x0c20_0010);
+        assert_eq!(
+            MatchOptions::from_request(first_move),
+            MatchOptions::default()
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// Pairs waiting players into games.
+// Players wait in the order they arrived and the two who have waited longest are matched, so
+// nobody can be skipped by players who connect after them.
+use std::collections::VecDeque;
+
+use crate::{
+    game_state::{GameState, GameStateTrait},
+    player::Player,
+    request::{DataRequest, Request},
+    roster::{Roster, RosterTrait},
+};
+
+#[derive(Debug, Default)]
+pub struct Matchmaker {
+    waiting: VecDeque<Player>,
+}
+
+pub trait MatchmakerTrait {
+    fn new() -> Self;
+    fn join(&mut self, player: Player) -> Option<(Player, Player)>;
+    fn leave(&mut self, player: &Player) -> bool;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+}
+
+impl MatchmakerTrait for Matchmaker {
+    fn new() -> Self {
+        Matchmaker::default()
+    }
+
+    /// Adds a player to the queue. Joining again while already waiting keeps the original place.
+    ///
+    /// # Arguments
+    ///
+    /// * `player` - The player looking for a game.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(Player, Player)>` - The two players who waited longest, in the order they
+    ///   joined, once there are two waiting.
+    fn join(&mut self, player: Player) -> Option<(Player, Player)> {
+        if !self.waiting.contains(&player) {
+            self.waiting.push_back(player);
+        }
+        if self.waiting.len() < 2 {
+            return None;
+        }
+        let first = self.waiting.pop_front()?;
+        let second = self.waiting.pop_front()?;
+        Some((first, second))
+    }
+
+    /// Takes a player out of the queue, e.g. when they disconnect before being matched.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the player was waiting.
+    fn leave(&mut self, player: &Player) -> bool {
+        let before = self.waiting.len();
+        self.waiting.retain(|waiting| waiting != player);
+        self.waiting.len() != before
+    }
+
+    fn len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+/// Creates the shared game for two matched players, on an empty board with `first` to move.
+/// The starting state counts as submitted by `second` so that `first` passes the check that
+/// players alternate.
+///
+/// # Arguments
+///
+/// * `first` - The player who moves first and plays X.
+/// * `second` - The player who moves second and plays O.
+///
+/// # Returns
+///
+/// * `GameState` - The starting state, stored under both players.
+pub fn new_match(first: Player, second: Player) -> GameState {
+    GameState::from_request(Request::new_data_request(false), second.clone())
+        .expect("the empty board is a valid request")
+        .with_roster(Roster::pair(first, second))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::T3p0Error, player::PlayerTrait};
+
+    fn player(id: u8) -> Player {
+        Player::from_bytes(&[id; 16])
+    }
+
+    #[test]
+    fn pairs_in_arrival_order() {
+        let mut matchmaker = Matchmaker::new();
+        assert_eq!(matchmaker.join(player(1)), None);
+        assert_eq!(matchmaker.join(player(2)), Some((player(1), player(2))));
+        assert!(matchmaker.is_empty());
+
+        assert_eq!(matchmaker.join(player(3)), None);
+        assert_eq!(matchmaker.join(player(4)), Some((player(3), player(4))));
+    }
+
+    #[test]
+    fn joining_twice_keeps_one_place() {
+        let mut matchmaker = Matchmaker::new();
+        assert_eq!(matchmaker.join(player(1)), None);
+        assert_eq!(matchmaker.join(player(1)), None);
+        assert_eq!(matchmaker.len(), 1);
+    }
+
+    #[test]
+    fn leaving_removes_from_queue() {
+        let mut matchmaker = Matchmaker::new();
+        matchmaker.join(player(1));
+        assert!(matchmaker.leave(&player(1)));
+        assert!(!matchmaker.leave(&player(1)));
+        assert_eq!(matchmaker.join(player(2)), None);
+        assert_eq!(matchmaker.join(player(3)), Some((player(2), player(3))));
+    }
+
+    #[test]
+    fn match_accepts_first_players_move_only() {
+        let game = new_match(player(1), player(2));
+        assert_eq!(game.to_request(), Request::new_data_request(false));
+        let first_move = Request::new_data_request(false)
+            .increment_turn_and_message()
+            .unwrap()
+            .set_board(1 << 4);
+        let by_first = GameState::from_request(first_move, player(1)).unwrap();
+        let by_second = GameState::from_request(first_move, player(2)).unwrap();
+        assert_eq!(game.validate_turn(&by_first), Ok(()));
+        assert_eq!(
+            game.validate_turn(&by_second),
+            Err(T3p0Error::WrongPlayerTurn)
+        );
+    }
+
+    #[test]
+    fn match_puts_first_player_on_first_side() {
+        let game = new_match(player(1), player(2));
+        let roster = game.get_roster().unwrap();
+        assert_eq!(roster.team(0), [player(1)]);
+        assert_eq!(roster.side_of(&player(1)), Some(0));
+        assert_eq!(roster.side_of(&player(2)), Some(1));
+    }
+}
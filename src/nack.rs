@@ -0,0 +1,310 @@
+// A NACK (negative acknowledgement) is sent by the server when it rejects a frame.
+// Instead of only saying "no", it attaches the server's authoritative state so the
+// client can repair its view of the game in a single round trip.
+
+// A NACK is two 32 bit unsigned integers sent back to back (8 bytes).
+// The first integer is the header, the second is the authoritative data request.
+
+/// |-------|--------------|
+/// | 1     | Message Type | Always set, like an Ok response.
+/// |-------|--------------|
+/// | 2-11  | Unused       |
+/// |-------|--------------|
+/// | 12    | NACK Flag    | Always set. This is one of the unused bits of a data request
+/// |       |              | so a NACK header can never be mistaken for an Ok or data frame.
+/// |-------|--------------|
+/// | 13    | Retryable    | Set if sending the same frame again later can succeed.
+/// |-------|--------------|
+/// | 14-16 | Unused       |
+/// |-------|--------------|
+/// | 17-24 | Backoff Hint | How long to wait before retrying, in units of 10ms. 0 for no hint.
+/// |-------|--------------|
+/// | 25-32 | Error Code   | See `NackCode`.
+/// |-------|--------------|
+///
+/// The second integer is a regular data request, see `request.rs`.
+///
+/// Clients should act on the retryable flag and backoff hint rather than on the error code, so
+/// new codes don't need client changes.
+use std::time::Duration;
+
+use crate::{
+    error::T3p0Error,
+    request::{Bits, Request},
+};
+
+/// Offset of the bit that marks a header as a NACK.
+pub(crate) const NACK_FLAG_OFFSET: u32 = 20;
+/// Offset of the bit that marks a rejected frame as worth sending again.
+pub(crate) const RETRYABLE_OFFSET: u32 = 19;
+/// Number of bits reserved for the error code.
+pub(crate) const CODE_RANGE: u32 = 8;
+/// Offset of the backoff hint, right after the error code.
+pub(crate) const BACKOFF_OFFSET: u32 = CODE_RANGE;
+/// Number of bits reserved for the backoff hint.
+pub(crate) const BACKOFF_RANGE: u32 = 8;
+/// The length of one unit of the backoff hint.
+pub const BACKOFF_UNIT: Duration = Duration::from_millis(10);
+
+/// The reason a frame was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum NackCode {
+    /// The frame was not 4 bytes long.
+    InvalidFrame = 1,
+    /// The frame failed `DataRequest::validate_request`.
+    InvalidRequest = 2,
+    /// The frame was well formed but is not a legal next move.
+    IllegalMove = 3,
+    /// The connection is sending frames faster than it is allowed to. The frame was dropped.
+    RateLimited = 4,
+}
+
+impl NackCode {
+    /// Every error code, in wire order.
+    pub const ALL: [NackCode; 4] = [
+        NackCode::InvalidFrame,
+        NackCode::InvalidRequest,
+        NackCode::IllegalMove,
+        NackCode::RateLimited,
+    ];
+
+    /// A short explanation of the error code for client authors.
+    pub fn description(&self) -> &'static str {
+        match self {
+            NackCode::InvalidFrame => "The frame was not 4 bytes long.",
+            NackCode::InvalidRequest => "The frame failed request validation.",
+            NackCode::IllegalMove => "The frame was well formed but is not a legal next move.",
+            NackCode::RateLimited => {
+                "Too many frames were sent too quickly. The frame was dropped, slow down."
+            }
+        }
+    }
+
+    /// Whether the same frame can succeed if it is sent again. Only a dropped frame can, the
+    /// others were read and rejected, so the client has to resync from the attached state.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, NackCode::RateLimited)
+    }
+
+    /// How long the server suggests waiting before a retry, zero if it has no suggestion.
+    pub fn backoff_hint(&self) -> Duration {
+        match self {
+            // The time the default rate limit of 20 frames per second takes to allow another frame.
+            NackCode::RateLimited => Duration::from_millis(50),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl TryFrom<u8> for NackCode {
+    type Error = T3p0Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(NackCode::InvalidFrame),
+            2 => Ok(NackCode::InvalidRequest),
+            3 => Ok(NackCode::IllegalMove),
+            4 => Ok(NackCode::RateLimited),
+            _ => Err(T3p0Error::UnknownNackCode),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Nack {
+    pub code: NackCode,
+    pub state: Request,
+    /// Whether the rejected frame can be sent again, see `NackCode::is_retryable`.
+    pub retryable: bool,
+    /// How long to wait before retrying, a multiple of `BACKOFF_UNIT` up to 2.55 seconds.
+    pub backoff: Duration,
+}
+
+pub trait NackTrait {
+    fn new(code: NackCode, state: Request) -> Self;
+    fn retry_after(&self) -> Option<Duration>;
+    fn header(&self) -> u32;
+    fn is_nack_header(header: u32) -> bool;
+    fn to_bytes(&self) -> [u8; 8];
+    fn from_bytes(bytes: &[u8; 8]) -> Result<Self, T3p0Error>
+    where
+        Self: Sized;
+}
+
+impl NackTrait for Nack {
+    /// Creates a NACK with the retry semantics of its error code.
+    fn new(code: NackCode, state: Request) -> Self {
+        Nack {
+            code,
+            state,
+            retryable: code.is_retryable(),
+            backoff: code.backoff_hint(),
+        }
+    }
+
+    /// Says when the rejected frame should be sent again.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Duration>` - How long to wait first, or `None` if the frame shouldn't be resent.
+    fn retry_after(&self) -> Option<Duration> {
+        self.retryable.then_some(self.backoff)
+    }
+
+    /// Builds the first 32 bit integer of the NACK.
+    ///
+    /// # Returns
+    ///
+    /// * `u32` - The header with the message type, NACK flag, retry semantics and error code set.
+    fn header(&self) -> u32 {
+        let backoff_units = (self.backoff.as_millis() / BACKOFF_UNIT.as_millis())
+            .min((1 << BACKOFF_RANGE) - 1) as u32;
+        1 << Bits::MessageType as u32
+            | 1 << NACK_FLAG_OFFSET
+            | u32::from(self.retryable) << RETRYABLE_OFFSET
+            | backoff_units << BACKOFF_OFFSET
+            | self.code as u32
+    }
+
+    /// Checks whether a 32 bit integer read from the wire is the start of a NACK.
+    /// If it is, the next 4 bytes on the wire are the authoritative state.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The first 32 bit integer of a frame.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the message type and NACK flag are both set.
+    fn is_nack_header(header: u32) -> bool {
+        let flags = 1 << Bits::MessageType as u32 | 1 << NACK_FLAG_OFFSET;
+        header & flags == flags
+    }
+
+    /// Serializes the NACK as two big endian 32 bit integers.
+    ///
+    /// # Returns
+    ///
+    /// * `[u8; 8]` - The header followed by the authoritative state.
+    fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&self.header().to_be_bytes());
+        bytes[4..].copy_from_slice(&self.state.0.to_be_bytes());
+        bytes
+    }
+
+    /// Parses a NACK from the 8 bytes read off the wire.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The header followed by the authoritative state.
+    ///
+    /// # Errors
+    ///
+    /// * `T3p0Error::NotANack` - If the header is not a NACK.
+    /// * `T3p0Error::UnknownNackCode` - If the error code is unknown.
+    fn from_bytes(bytes: &[u8; 8]) -> Result<Self, T3p0Error> {
+        let mut header = [0u8; 4];
+        header.copy_from_slice(&bytes[..4]);
+        let header = u32::from_be_bytes(header);
+        if !Nack::is_nack_header(header) {
+            return Err(T3p0Error::NotANack);
+        }
+
+        let mut state = [0u8; 4];
+        state.copy_from_slice(&bytes[4..]);
+        let backoff_units = header >> BACKOFF_OFFSET & ((1 << BACKOFF_RANGE) - 1);
+        Ok(Nack {
+            code: NackCode::try_from((header & ((1 << CODE_RANGE) - 1)) as u8)?,
+            state: Request(u32::from_be_bytes(state)),
+            retryable: header & 1 << RETRYABLE_OFFSET != 0,
+            backoff: BACKOFF_UNIT * backoff_units,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::DataRequest;
+
+    #[test]
+    fn header_layout() {
+        let nack = Nack::new(NackCode::IllegalMove, Request(0));
+        assert_eq!(
+            nack.header(),
+            1 << Bits::MessageType as u32 | 1 << NACK_FLAG_OFFSET | 3
+        );
+    }
+
+    #[test]
+    fn header_is_not_ok_response() {
+        let nack = Nack::new(NackCode::InvalidRequest, Request(0));
+        assert!(!Request(nack.header()).is_ok_response());
+        assert!(!Nack::is_nack_header(Request::new_data_request(true).0));
+    }
+
+    #[test]
+    fn round_trip() {
+        let state = Request(0b101 | 1 << Bits::MessageNumber as u32);
+        let nack = Nack::new(NackCode::InvalidRequest, state);
+        let parsed = Nack::from_bytes(&nack.to_bytes());
+        assert_eq!(parsed, Ok(nack));
+    }
+
+    #[test]
+    fn rate_limited_is_retryable_with_backoff() {
+        let nack = Nack::new(NackCode::RateLimited, Request(0));
+        assert_eq!(nack.retry_after(), Some(Duration::from_millis(50)));
+        assert_eq!(
+            nack.header(),
+            1 << Bits::MessageType as u32
+                | 1 << NACK_FLAG_OFFSET
+                | 1 << RETRYABLE_OFFSET
+                | 5 << BACKOFF_OFFSET
+                | 4
+        );
+        assert_eq!(
+            Nack::new(NackCode::IllegalMove, Request(0)).retry_after(),
+            None
+        );
+    }
+
+    #[test]
+    fn retry_semantics_come_from_the_header() {
+        // A server may retry codes differently, the client follows the header.
+        let nack = Nack {
+            retryable: true,
+            backoff: Duration::from_millis(1230),
+            ..Nack::new(NackCode::InvalidFrame, Request(0))
+        };
+        let parsed = Nack::from_bytes(&nack.to_bytes()).unwrap();
+        assert_eq!(parsed.retry_after(), Some(Duration::from_millis(1230)));
+    }
+
+    #[test]
+    fn backoff_saturates() {
+        let nack = Nack {
+            backoff: Duration::from_secs(60),
+            ..Nack::new(NackCode::RateLimited, Request(0))
+        };
+        let parsed = Nack::from_bytes(&nack.to_bytes()).unwrap();
+        assert_eq!(parsed.backoff, Duration::from_millis(2550));
+    }
+
+    #[test]
+    fn from_bytes_not_a_nack() {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&Request::new_data_request(true).0.to_be_bytes());
+        assert_eq!(Nack::from_bytes(&bytes), Err(T3p0Error::NotANack));
+    }
+
+    #[test]
+    fn from_bytes_unknown_code() {
+        let header: u32 = 1 << Bits::MessageType as u32 | 1 << NACK_FLAG_OFFSET | 0xFF;
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&header.to_be_bytes());
+        assert_eq!(Nack::from_bytes(&bytes), Err(T3p0Error::UnknownNackCode));
+    }
+}
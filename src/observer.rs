@@ -0,0 +1,92 @@
+// Plain text rendering for the read-only observer port.
+// Anyone with telnet or netcat can connect, type a player id and watch that player's board.
+use crate::request::{DataRequest, Request};
+
+/// Draws a board as ASCII art with the turn information above it.
+/// Squares are drawn as `X`, `O` or `.` for empty, lines end in `\r\n` for telnet.
+///
+/// # Arguments
+///
+/// * `request` - The data request holding the board.
+///
+/// # Returns
+///
+/// * `String` - The rendered board.
+pub fn render_board(request: &Request) -> String {
+    let (x_marks, o_marks) = (request.get_x_marks(), request.get_o_marks());
+    let mut output = format!(
+        "Turn {} | Message {} | {} to move\r\n",
+        request.get_turn(),
+        request.get_message_number(),
+        if request.get_is_p2_turn() {
+            "Player 2"
+        } else {
+            "Player 1"
+        }
+    );
+    for row in 0..3 {
+        let squares: Vec<String> = (0..3)
+            .map(|column| {
+                let square = row * 3 + column;
+                if (x_marks >> square) & 1 == 1 {
+                    " X ".to_string()
+                } else if (o_marks >> square) & 1 == 1 {
+                    " O ".to_string()
+                } else {
+                    " . ".to_string()
+                }
+            })
+            .collect();
+        output.push_str(&squares.join("|"));
+        output.push_str("\r\n");
+        if row < 2 {
+            output.push_str("-----------\r\n");
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_empty_board() {
+        let request = Request::new_data_request(false);
+        assert_eq!(
+            render_board(&request),
+            "Turn 0 | Message 0 | Player 1 to move\r\n \
+             . | . | . \r\n-----------\r\n \
+             . | . | . \r\n-----------\r\n \
+             . | . | . \r\n"
+        );
+    }
+
+    #[test]
+    fn render_corners() {
+        let request = Request::new_data_request(false)
+            .set_board(0b100000001)
+            .set_turn(2)
+            .set_message_number(2);
+        let rendered = render_board(&request);
+        assert!(rendered.starts_with("Turn 2 | Message 2 | Player 1 to move\r\n"));
+        assert!(rendered.contains(" X | . | . \r\n"));
+        assert!(rendered.ends_with(" . | . | X \r\n"));
+    }
+
+    #[test]
+    fn render_o_marks() {
+        let request = Request::new_data_request(false)
+            .set_board(0b000_010_001)
+            .set_o_marks(0b000_010_000);
+        let rendered = render_board(&request);
+        assert!(rendered.contains(" X | . | . \r\n"));
+        assert!(rendered.contains(" . | O | . \r\n"));
+    }
+
+    #[test]
+    fn render_player_two() {
+        let request = Request::new_data_request(false).set_p2_turn(true);
+        assert!(render_board(&request).contains("Player 2 to move"));
+    }
+}
@@ -4,12 +4,14 @@ use uuid::Uuid;
 pub struct Player(Uuid);
 
 pub trait PlayerTrait {
+    #[cfg(feature = "rand")]
     fn new() -> Self;
     fn get_id(&self) -> &Uuid;
     fn from_bytes(bytes: &[u8; 16]) -> Self;
 }
 
 impl PlayerTrait for Player {
+    #[cfg(feature = "rand")]
     fn new() -> Self {
         Player(Uuid::new_v4())
     }
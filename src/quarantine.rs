@@ -0,0 +1,226 @@
+// Frames that fail validation can be written to a quarantine log so protocol bugs reported by
+// client authors can be diagnosed from what the server actually received.
+// The log is plain text, one record per line, and is capped in size. When the cap is reached the
+// current file is rotated to `<path>.1` (replacing any older rotation) and a new file is started.
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    request::{DataRequest, Request},
+    Player, PlayerTrait,
+};
+
+/// Everything known about a single rejected frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantineRecord {
+    pub raw: Vec<u8>,
+    pub player: Player,
+    pub peer: Option<SocketAddr>,
+    pub reason: String,
+}
+
+pub trait QuarantineRecordTrait {
+    fn new(raw: &[u8], player: &Player, peer: Option<SocketAddr>, reason: &str) -> Self;
+    fn to_line(&self) -> String;
+}
+
+impl QuarantineRecordTrait for QuarantineRecord {
+    fn new(raw: &[u8], player: &Player, peer: Option<SocketAddr>, reason: &str) -> Self {
+        QuarantineRecord {
+            raw: raw.to_vec(),
+            player: player.clone(),
+            peer,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Formats the record as a single log line.
+    /// If the raw bytes are a full 4 byte frame the decoded fields are included as well.
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The record without a trailing newline.
+    fn to_line(&self) -> String {
+        let raw: String = self.raw.iter().map(|b| format!("{:02x}", b)).collect();
+        let peer = match self.peer {
+            Some(peer) => peer.to_string(),
+            None => "-".to_string(),
+        };
+        let mut line = format!("player={} peer={} raw={}", self.player.get_id(), peer, raw);
+        if let Ok(bytes) = <[u8; 4]>::try_from(self.raw.as_slice()) {
+            let request = Request(u32::from_be_bytes(bytes));
+            line.push_str(&format!(
+                " turn={} message={} p2_turn={} board={:09b}",
+                request.get_turn(),
+                request.get_message_number(),
+                request.get_is_p2_turn(),
+                request.get_board_state()
+            ));
+        }
+        line.push_str(&format!(" reason={:?}", self.reason));
+        line
+    }
+}
+
+#[derive(Debug)]
+pub struct Quarantine {
+    path: PathBuf,
+    max_bytes: u64,
+    // Serializes appends and rotation between connections.
+    lock: Mutex<()>,
+}
+
+pub trait QuarantineTrait {
+    fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self;
+    fn record(&self, record: &QuarantineRecord) -> io::Result<()>;
+    fn read(&self) -> io::Result<Vec<String>>;
+}
+
+impl Quarantine {
+    fn rotated_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        PathBuf::from(path)
+    }
+}
+
+/// Reads the lines of a file, treating a missing file as empty.
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.lines().map(String::from).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+impl QuarantineTrait for Quarantine {
+    /// Creates a quarantine log. Nothing is written until the first record.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file records are appended to.
+    /// * `max_bytes` - The size the file may reach before it is rotated.
+    fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Quarantine {
+            path: path.into(),
+            max_bytes,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends a record, rotating the file first if it has reached its size cap.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If the file couldn't be rotated or written to.
+    fn record(&self, record: &QuarantineRecord) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            if metadata.len() >= self.max_bytes {
+                fs::rename(&self.path, self.rotated_path())?;
+            }
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", record.to_line())
+    }
+
+    /// Reads every record still on disk, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If either file exists but couldn't be read.
+    fn read(&self) -> io::Result<Vec<String>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut lines = read_lines(&self.rotated_path())?;
+        lines.extend(read_lines(&self.path)?);
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "t3p0-quarantine-{}-{}.log",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn cleanup(quarantine: &Quarantine) {
+        let _ = fs::remove_file(&quarantine.path);
+        let _ = fs::remove_file(quarantine.rotated_path());
+    }
+
+    #[test]
+    fn to_line_decodes_frame() {
+        let player = Player::from_bytes(&[0u8; 16]);
+        let raw = Request(1 << 21 | 0b1).0.to_be_bytes();
+        let record = QuarantineRecord::new(&raw, &player, None, "bad");
+        assert_eq!(
+            record.to_line(),
+            "player=00000000-0000-0000-0000-000000000000 peer=- raw=00200001 \
+             turn=0 message=1 p2_turn=false board=000000001 reason=\"bad\""
+        );
+    }
+
+    #[test]
+    fn to_line_short_frame() {
+        let player = Player::from_bytes(&[0u8; 16]);
+        let peer = "127.0.0.1:9000".parse().ok();
+        let record = QuarantineRecord::new(&[0xab, 0xcd], &player, peer, "short");
+        assert_eq!(
+            record.to_line(),
+            "player=00000000-0000-0000-0000-000000000000 peer=127.0.0.1:9000 raw=abcd reason=\"short\""
+        );
+    }
+
+    #[test]
+    fn record_and_read() {
+        let quarantine = Quarantine::new(temp_path("record"), 1024);
+        let record =
+            QuarantineRecord::new(&[1, 2, 3, 4], &Player::from_bytes(&[1; 16]), None, "first");
+        quarantine.record(&record).unwrap();
+        let record =
+            QuarantineRecord::new(&[5, 6, 7, 8], &Player::from_bytes(&[2; 16]), None, "second");
+        quarantine.record(&record).unwrap();
+
+        let lines = quarantine.read().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("reason=\"first\""));
+        assert!(lines[1].ends_with("reason=\"second\""));
+        cleanup(&quarantine);
+    }
+
+    #[test]
+    fn record_rotates_at_cap() {
+        // Every record is larger than the cap so each write rotates the previous one out.
+        let quarantine = Quarantine::new(temp_path("rotate"), 1);
+        for reason in ["first", "second", "third"] {
+            let record = QuarantineRecord::new(&[0], &Player::from_bytes(&[1; 16]), None, reason);
+            quarantine.record(&record).unwrap();
+        }
+
+        let lines = quarantine.read().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("reason=\"second\""));
+        assert!(lines[1].ends_with("reason=\"third\""));
+        cleanup(&quarantine);
+    }
+
+    #[test]
+    fn read_missing_file() {
+        let quarantine = Quarantine::new(temp_path("missing"), 1024);
+        assert!(quarantine.read().unwrap().is_empty());
+    }
+}
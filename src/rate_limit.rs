@@ -0,0 +1,100 @@
+// A token bucket used to cap how many frames a single connection may send.
+// The bucket starts full, every frame takes a token and tokens are refilled at a steady rate
+// up to the bucket's capacity, so short bursts are allowed but a sustained flood is not.
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+pub trait TokenBucketTrait {
+    fn new(capacity: u32, refill_per_second: u32) -> Self;
+    fn try_take(&mut self) -> bool;
+    fn try_take_at(&mut self, now: Instant) -> bool;
+}
+
+impl TokenBucketTrait for TokenBucket {
+    /// Creates a full bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The largest burst of frames allowed at once.
+    /// * `refill_per_second` - The sustained number of frames allowed per second.
+    fn new(capacity: u32, refill_per_second: u32) -> Self {
+        TokenBucket {
+            capacity: f64::from(capacity),
+            refill_per_second: f64::from(refill_per_second),
+            tokens: f64::from(capacity),
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token if one is available.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the frame is allowed, false if the connection is over its limit.
+    fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    /// Same as `try_take` but with the current time passed in.
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn allows_burst_up_to_capacity() {
+        let mut bucket = TokenBucket::new(3, 1);
+        let now = bucket.last_refill;
+        assert!(bucket.try_take_at(now));
+        assert!(bucket.try_take_at(now));
+        assert!(bucket.try_take_at(now));
+        assert!(!bucket.try_take_at(now));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let mut bucket = TokenBucket::new(1, 10);
+        let now = bucket.last_refill;
+        assert!(bucket.try_take_at(now));
+        assert!(!bucket.try_take_at(now + Duration::from_millis(50)));
+        assert!(bucket.try_take_at(now + Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn never_exceeds_capacity() {
+        let mut bucket = TokenBucket::new(2, 100);
+        let later = bucket.last_refill + Duration::from_secs(60);
+        assert!(bucket.try_take_at(later));
+        assert!(bucket.try_take_at(later));
+        assert!(!bucket.try_take_at(later));
+    }
+
+    #[test]
+    fn time_going_backwards_is_ignored() {
+        let mut bucket = TokenBucket::new(1, 1);
+        let now = bucket.last_refill;
+        assert!(bucket.try_take_at(now + Duration::from_secs(1)));
+        assert!(!bucket.try_take_at(now));
+    }
+}
@@ -11,7 +11,9 @@
 // For example, 0x000000001 is the top left corner and 0x100000000 is the bottom right corner.
 
 // How do we represent the board state if there are three possible states, empty, X, and O?
-// The server should send the board state as the opponent sees it.
+// The board state says which squares are filled, and a second 9 bit mask in the same layout says
+// which of those hold O, the second player's mark. A filled square that isn't in the O mask is X.
+// Clients that only look at the board state keep working, they just can't tell the marks apart.
 
 /// |----|--------------|
 /// | 1  | Message Type | There are two possible message types. Data and Ok.
@@ -30,10 +32,14 @@
 /// | 11 |              |
 /// |----|--------------|
 /// | 12 | Unused       |
-/// | 13 |              |
-/// | 14 |              |
-/// | 15 |              |
-/// | 16 |              |
+/// |----|--------------|
+/// | 13 | Second Side  | Only set by the server, in the empty board it sends when a match
+/// |    |              | starts, to tell the receiver that they play second.
+/// |----|--------------|
+/// | 14 | Dry Run      | Asks whether the move would be legal without making it.
+/// |----|--------------|
+/// | 15 | O Marks      | Same layout as the board state. Set for squares holding
+/// | 16 |              | the second player's mark, and only allowed on filled squares.
 /// | 17 |              |
 /// | 18 |              |
 /// | 19 |              |
@@ -52,10 +58,14 @@
 /// | 31 |              | is represented.
 /// | 32 |              |
 /// |----|--------------|
+use crate::error::T3p0Error;
 
 #[derive(Debug)]
 #[repr(u32)]
 pub enum Bits {
+    OMarks = 9u32,
+    DryRun = 18u32,
+    SecondSide = 19u32,
     MessageNumber = 21u32,
     P2Turn = 26u32,
     TurnOffset = 27u32,
@@ -64,7 +74,7 @@ pub enum Bits {
 
 #[derive(Debug)]
 #[repr(u32)]
-enum Ranges {
+pub(crate) enum Ranges {
     Board = 9u32,
     MessageNumber = 5u32,
     Turn = 4u32,
@@ -72,16 +82,45 @@ enum Ranges {
 
 pub trait DataRequest {
     fn new_data_request(is_ok_response: bool) -> Self;
-    fn validate_request(&self) -> Result<(), &'static str>;
+    fn validate_request(&self) -> Result<(), T3p0Error>;
     fn swap_player(&self) -> Self;
     fn get_turn(&self) -> u8;
     fn get_message_number(&self) -> u8;
     fn get_board_state(&self) -> u16;
+    fn get_x_marks(&self) -> u16;
+    fn get_o_marks(&self) -> u16;
     fn get_is_p2_turn(&self) -> bool;
-    fn increment_turn_and_message(&self) -> Result<Self, &'static str>
+    fn increment_turn_and_message(&self) -> Result<Self, T3p0Error>
     where
         Self: Sized;
     fn is_ok_response(&self) -> bool;
+    fn set_turn(&self, turn: u8) -> Self;
+    fn set_message_number(&self, message_number: u8) -> Self;
+    fn set_board(&self, board: u16) -> Self;
+    fn set_o_marks(&self, o_marks: u16) -> Self;
+    fn set_p2_turn(&self, is_p2_turn: bool) -> Self;
+    fn is_dry_run(&self) -> bool;
+    fn set_dry_run(&self, is_dry_run: bool) -> Self;
+    fn is_second_side(&self) -> bool;
+    fn set_second_side(&self, is_second_side: bool) -> Self;
+}
+
+/// Clears a range of bits and writes a value into it.
+/// Any bits of `value` that don't fit in the range are discarded so neighbouring fields are never touched.
+///
+/// # Arguments
+///
+/// * `request` - The u32 to write into.
+/// * `offset` - The offset of the least significant bit of the range.
+/// * `range` - The number of bits in the range.
+/// * `value` - The value to write.
+///
+/// # Returns
+///
+/// * `u32` - The u32 with the range replaced by `value`.
+fn write_range(request: u32, offset: u32, range: u32, value: u32) -> u32 {
+    let mask = ((1 << range) - 1) << offset;
+    (request & !mask) | ((value << offset) & mask)
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -135,6 +174,24 @@ impl DataRequest for Request {
         (self.0 & ((1 << Ranges::Board as u32) - 1)) as u16
     }
 
+    /// Gets the squares holding the first player's mark.
+    ///
+    /// # Returns
+    ///
+    /// * `u16` - The filled squares that aren't in the O mask, laid out like `get_board_state`.
+    fn get_x_marks(&self) -> u16 {
+        self.get_board_state() & !self.get_o_marks()
+    }
+
+    /// Gets the squares holding the second player's mark.
+    ///
+    /// # Returns
+    ///
+    /// * `u16` - The O mask, laid out like `get_board_state`.
+    fn get_o_marks(&self) -> u16 {
+        ((self.0 >> Bits::OMarks as u32) & ((1 << Ranges::Board as u32) - 1)) as u16
+    }
+
     /// Gets whether it's the second player's turn.
     ///
     /// # Returns
@@ -155,18 +212,16 @@ impl DataRequest for Request {
         ((self.0 >> Bits::MessageNumber as u32) & ((1 << Ranges::MessageNumber as u32) - 1)) as u8
     }
 
-    /// Switches the bit that represents whose turn it is and flips the state of the board.
+    /// Switches the bit that represents whose turn it is and swaps the X and O marks.
+    /// Filled squares stay filled, so a valid board stays valid and swapping twice gives back the
+    /// original request.
     ///
     /// # Returns
     ///
     /// * `u32` - A new u32 that represents the exact board state but it's flipped to the other users view.
     fn swap_player(&self) -> Self {
-        let mut output = self.0;
-        for i in 0..Ranges::Board as usize {
-            output ^= 1 << i;
-        }
-        output ^= 1 << Bits::P2Turn as u32;
-        Request(output)
+        self.set_o_marks(self.get_x_marks())
+            .set_p2_turn(!self.get_is_p2_turn())
     }
 
     /// Increments the turn and message number by 1.
@@ -176,73 +231,225 @@ impl DataRequest for Request {
     ///
     /// # Returns
     ///
-    /// * `Result<Self, &'static str>` - A result that is either the new request or an error message.
+    /// * `Result<Self, T3p0Error>` - A result that is either the new request or an error.
     ///
     /// # Errors
     ///
-    /// * `&'static str` - An error message that describes why the request is invalid.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<Self, &'static str>` - A result that is either the new request or an error message.
-    fn increment_turn_and_message(&self) -> Result<Self, &'static str> {
-        let turn = self.get_turn();
-        let message_number = self.get_message_number();
-        if message_number + 1 >= 27 {
-            return Err("Trying to increment message number past maximum value.");
-        }
-        // First clear out that set of bits then | that number plus 1
-        let mut output = self.0 ^ (u32::from(turn) << Bits::TurnOffset as u32);
-        output |= ((u32::from(turn) + 1) % 9) << Bits::TurnOffset as u32;
-        output ^= u32::from(message_number) << Bits::MessageNumber as u32;
-        output |= u32::from(message_number + 1) << Bits::MessageNumber as u32;
-        output ^= 1 << Bits::P2Turn as u32;
-        Ok(Request(output))
+    /// * `T3p0Error::MessageNumberOverflow` - If the message number would pass the maximum.
+    /// * `T3p0Error::TurnOverflow` - If the turn number can't be incremented.
+    fn increment_turn_and_message(&self) -> Result<Self, T3p0Error> {
+        let message_number = self
+            .get_message_number()
+            .checked_add(1)
+            .filter(|&next| next < 27)
+            .ok_or(T3p0Error::MessageNumberOverflow)?;
+        // The turn field is 4 bits so it never reaches u8::MAX, but a turn past 8 is already invalid.
+        let turn = self
+            .get_turn()
+            .checked_add(1)
+            .ok_or(T3p0Error::TurnOverflow)?
+            % 9;
+        Ok(self
+            .set_turn(turn)
+            .set_message_number(message_number)
+            .set_p2_turn(!self.get_is_p2_turn()))
     }
 
     /// Validates the request to make sure that the turn and message number are in sync.
     ///
     /// # Returns
     ///
-    /// * `Result<(), &'static str>` - A result that is either an empty result or an error message.
+    /// * `Result<(), T3p0Error>` - A result that is either an empty result or an error.
     ///
     /// # Errors
     ///
-    /// * `&'static str` - An error message that describes why the request is invalid.
-    fn validate_request(&self) -> Result<(), &'static str> {
+    /// * `T3p0Error` - The first rule the request breaks.
+    fn validate_request(&self) -> Result<(), T3p0Error> {
         if self.get_message_number() >= 27 {
-            return Err("Trying to increment message number past maximum value.");
+            return Err(T3p0Error::MessageNumberOverflow);
         }
 
         if self.get_turn() >= 9 {
-            return Err("Trying to increment turn number past maximum value.");
+            return Err(T3p0Error::TurnOverflow);
+        }
+
+        if self.get_o_marks() & !self.get_board_state() != 0 {
+            return Err(T3p0Error::OMarkOnEmptySquare);
         }
 
         if self.get_message_number() < self.get_turn() {
-            return Err("Message number is less than turn number.");
+            return Err(T3p0Error::MessageBehindTurn);
         }
-        println!(
-            "Turn: {}, Message: {}",
-            self.get_turn(),
-            self.get_message_number()
-        );
         if self.get_message_number() % 9 != self.get_turn() {
-            return Err("Turn number and message number are not in sync.");
+            return Err(T3p0Error::TurnOutOfSync);
         }
 
-        if self.get_message_number() % 2 == 0 && self.get_is_p2_turn() {
-            return Err("Player 2 is trying to make a move on player 1's turn.");
+        if self.get_message_number().is_multiple_of(2) && self.get_is_p2_turn() {
+            return Err(T3p0Error::PlayerTwoOutOfTurn);
         }
 
         if self.get_message_number() % 2 == 1 && !self.get_is_p2_turn() {
-            return Err("Player 1 is trying to make a move on player 2's turn.");
+            return Err(T3p0Error::PlayerOneOutOfTurn);
+        }
+
+        // A game starts on an empty board and the first move places exactly one mark.
+        // Without this a client could pass its first move or start from a pre-filled board.
+        // Turn 0 later in a match is the ninth move, whose board is full.
+        if self.get_message_number() == 0 && self.get_board_state() != 0 {
+            return Err(T3p0Error::BoardNotEmptyAtStart);
+        }
+
+        if self.get_turn() == 1 && self.get_board_state().count_ones() != 1 {
+            return Err(T3p0Error::FirstMoveNotOneMark);
         }
 
         Ok(())
     }
 
     fn is_ok_response(&self) -> bool {
-        return self.0 & u32::MAX == 1 << Bits::MessageType as u32;
+        self.0 == 1 << Bits::MessageType as u32
+    }
+
+    /// Sets the turn number.
+    /// Only the lowest 4 bits of `turn` are written, the rest of the request is left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `turn` - The new turn number.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the turn replaced.
+    fn set_turn(&self, turn: u8) -> Self {
+        Request(write_range(
+            self.0,
+            Bits::TurnOffset as u32,
+            Ranges::Turn as u32,
+            u32::from(turn),
+        ))
+    }
+
+    /// Sets the message number.
+    /// Only the lowest 5 bits of `message_number` are written, the rest of the request is left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_number` - The new message number.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the message number replaced.
+    fn set_message_number(&self, message_number: u8) -> Self {
+        Request(write_range(
+            self.0,
+            Bits::MessageNumber as u32,
+            Ranges::MessageNumber as u32,
+            u32::from(message_number),
+        ))
+    }
+
+    /// Sets the board state.
+    /// Only the lowest 9 bits of `board` are written, the rest of the request is left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `board` - The new board state, laid out the same as `get_board_state`.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the board replaced.
+    fn set_board(&self, board: u16) -> Self {
+        Request(write_range(
+            self.0,
+            0,
+            Ranges::Board as u32,
+            u32::from(board),
+        ))
+    }
+
+    /// Sets which squares hold the second player's mark.
+    /// Only the lowest 9 bits of `o_marks` are written, the rest of the request is left untouched.
+    /// The squares must also be set in the board state for the request to be valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `o_marks` - The O mask, laid out the same as `get_board_state`.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the O mask replaced.
+    fn set_o_marks(&self, o_marks: u16) -> Self {
+        Request(write_range(
+            self.0,
+            Bits::OMarks as u32,
+            Ranges::Board as u32,
+            u32::from(o_marks),
+        ))
+    }
+
+    /// Sets whether it's the second player's turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_p2_turn` - True if it's player 2's turn and false if it's player 1.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the player turn bit replaced.
+    fn set_p2_turn(&self, is_p2_turn: bool) -> Self {
+        Request(write_range(
+            self.0,
+            Bits::P2Turn as u32,
+            1,
+            u32::from(is_p2_turn),
+        ))
+    }
+
+    /// Returns true if the client only wants to know whether the move is legal.
+    /// The server answers a dry run with an Ok response or an IllegalMove NACK and never applies it.
+    fn is_dry_run(&self) -> bool {
+        !self.is_ok_response() && (self.0 >> Bits::DryRun as u32) & 1 == 1
+    }
+
+    /// Sets whether the request is a dry run.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_dry_run` - True to ask about the move without making it.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the dry run bit replaced.
+    fn set_dry_run(&self, is_dry_run: bool) -> Self {
+        Request(write_range(
+            self.0,
+            Bits::DryRun as u32,
+            1,
+            u32::from(is_dry_run),
+        ))
+    }
+
+    /// Returns true if the match start frame tells the receiver they play second, placing O.
+    fn is_second_side(&self) -> bool {
+        !self.is_ok_response() && (self.0 >> Bits::SecondSide as u32) & 1 == 1
+    }
+
+    /// Sets whether the receiver of a match start frame plays second.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_second_side` - True for the player who places O.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - A new request with the second side bit replaced.
+    fn set_second_side(&self, is_second_side: bool) -> Self {
+        Request(write_range(
+            self.0,
+            Bits::SecondSide as u32,
+            1,
+            u32::from(is_second_side),
+        ))
     }
 }
 
@@ -347,7 +554,7 @@ mod tests {
         // If the msb is 1, then it's player 1's turn
         let r = Request(0b1 << Bits::P2Turn as u32);
         let is_p2_turn = r.get_is_p2_turn();
-        assert_eq!(is_p2_turn, true);
+        assert!(is_p2_turn);
     }
 
     #[test]
@@ -355,7 +562,7 @@ mod tests {
         // If the msb is 0, then it's player 2's turn
         let r = Request(u32::MAX ^ (1 << Bits::P2Turn as u32));
         let is_p2_turn = r.get_is_p2_turn();
-        assert_eq!(is_p2_turn, false);
+        assert!(!is_p2_turn);
     }
 
     #[test]
@@ -397,27 +604,47 @@ mod tests {
 
     #[test]
     fn test_swap_player() {
-        // All zeros should be all ones
-        let r = Request(0);
+        // The X in the top left becomes an O and the O in the centre becomes an X
+        let r = Request(0)
+            .set_board(0b000_010_001)
+            .set_o_marks(0b000_010_000);
         let swapped = r.swap_player();
-        assert_eq!(swapped, 0 | (1 << Bits::P2Turn as u32) | (1 << 9) - 1);
+        assert_eq!(swapped.get_board_state(), 0b000_010_001);
+        assert_eq!(swapped.get_o_marks(), 0b000_000_001);
+        assert!(swapped.get_is_p2_turn());
     }
 
     #[test]
     fn test_swap_player_from_all_ones() {
-        // All ones should be all zeros
+        // A full board of O marks becomes a full board of X marks
         let r = Request(u32::MAX);
         let swapped = r.swap_player();
-        assert_eq!(swapped, r.0 ^ (1 << Bits::P2Turn as u32) ^ (1 << 9) - 1);
+        assert_eq!(
+            swapped,
+            r.0 ^ (1 << Bits::P2Turn as u32) ^ (((1 << 9) - 1) << Bits::OMarks as u32)
+        );
     }
 
     #[test]
     fn test_swap_player_turn_separate_from_board() {
-        // All zeros except the msb should be all zeros except the lsb
+        // If the only bit that was 1 was the player turn, then the empty board stays empty.
         let r = Request(1 << Bits::P2Turn as u32);
         let swapped = r.swap_player();
-        // If the only bit that was 1 was the player turn but, then it should be 0 and the board should be all 1s.
-        assert_eq!(swapped, (1 << Ranges::Board as u32) - 1);
+        assert_eq!(swapped, 0);
+    }
+
+    #[test]
+    fn test_swap_player_round_trip() {
+        let r = Request::new_data_request(false)
+            .set_turn(2)
+            .set_message_number(2)
+            .set_board(0b100_010_001)
+            .set_o_marks(0b000_010_000);
+        assert_eq!(r.validate_request(), Ok(()));
+        let swapped = r.swap_player();
+        assert_eq!(swapped.get_x_marks(), r.get_o_marks());
+        assert_eq!(swapped.get_o_marks(), r.get_x_marks());
+        assert_eq!(swapped.swap_player(), r);
     }
 
     #[test]
@@ -476,7 +703,7 @@ mod tests {
             r = match r.increment_turn_and_message() {
                 Ok(r) => r,
                 Err(e) => {
-                    assert_eq!(e, "Trying to increment message number past maximum value.");
+                    assert_eq!(e, T3p0Error::MessageNumberOverflow);
                     break;
                 }
             };
@@ -491,7 +718,7 @@ mod tests {
             r = match r.increment_turn_and_message() {
                 Ok(r) => r,
                 Err(e) => {
-                    assert_eq!(e, "Trying to increment message number past maximum value.");
+                    assert_eq!(e, T3p0Error::MessageNumberOverflow);
                     break;
                 }
             };
@@ -499,6 +726,61 @@ mod tests {
         assert!(r.increment_turn_and_message().is_err());
     }
 
+    #[test]
+    fn increment_turn_and_message_at_field_maximums() {
+        // Every field at its largest value must be an error, never a panic or a wrap into the next field.
+        let r = Request::new_data_request(false)
+            .set_turn(15)
+            .set_message_number(31)
+            .set_board(0x1FF);
+        assert!(r.increment_turn_and_message().is_err());
+
+        let r = Request::new_data_request(false)
+            .set_turn(15)
+            .set_message_number(9);
+        let incremented = r.increment_turn_and_message().unwrap();
+        assert_eq!(incremented.get_turn(), 7);
+        assert_eq!(incremented.get_message_number(), 10);
+
+        let r = Request::new_data_request(false).set_message_number(26);
+        assert!(r.increment_turn_and_message().is_err());
+    }
+
+    #[test]
+    fn setters_mask_values_past_field_maximums() {
+        let r = Request::new_data_request(false);
+        assert_eq!(r.set_turn(u8::MAX).get_turn(), 15);
+        assert_eq!(r.set_turn(u8::MAX).get_message_number(), 0);
+        assert_eq!(r.set_message_number(u8::MAX).get_message_number(), 31);
+        assert_eq!(r.set_message_number(u8::MAX).get_turn(), 0);
+        assert_eq!(r.set_board(u16::MAX).get_board_state(), 0x1FF);
+        assert_eq!(r.set_board(u16::MAX).get_message_number(), 0);
+    }
+
+    #[test]
+    fn marks() {
+        let r = Request::new_data_request(false)
+            .set_board(0b000_010_011)
+            .set_o_marks(0b000_010_000);
+        assert_eq!(r.get_board_state(), 0b000_010_011);
+        assert_eq!(r.get_o_marks(), 0b000_010_000);
+        assert_eq!(r.get_x_marks(), 0b000_000_011);
+        // The O mask sits in the unused bits, clear of every other field.
+        assert_eq!(r.set_o_marks(u16::MAX).get_board_state(), 0b000_010_011);
+        assert_eq!(r.set_o_marks(u16::MAX).0 >> Bits::DryRun as u32, 0);
+    }
+
+    #[test]
+    fn validate_request_o_marks_on_empty_square() {
+        let r = Request::new_data_request(false)
+            .set_turn(2)
+            .set_message_number(2)
+            .set_board(0b011)
+            .set_o_marks(0b110);
+        assert!(r.validate_request().is_err());
+        assert!(r.set_o_marks(0b010).validate_request().is_ok());
+    }
+
     #[test]
     fn validate_request() {
         let r = Request::new_data_request(false);
@@ -545,7 +827,8 @@ mod tests {
         let r = Request(
             r.0 | 1 << Bits::P2Turn as u32
                 | 1 << Bits::MessageNumber as u32
-                | 1 << Bits::TurnOffset as u32,
+                | 1 << Bits::TurnOffset as u32
+                | 1,
         );
         assert!(r.validate_request().is_ok());
     }
@@ -555,21 +838,436 @@ mod tests {
         let r = Request::new_data_request(false);
         let r1 = Request(r.0 | 9 << Bits::MessageNumber as u32 | 1 << Bits::P2Turn as u32);
         assert!(r1.validate_request().is_ok());
-        let r2 = Request(r.0 | 10 << Bits::MessageNumber as u32 | 1 << Bits::TurnOffset as u32);
+        let r2 = Request(r.0 | 10 << Bits::MessageNumber as u32 | 1 << Bits::TurnOffset as u32 | 1);
         assert!(r2.validate_request().is_ok());
     }
 
+    #[test]
+    fn validate_request_prefilled_new_game() {
+        let r = Request::new_data_request(false).set_board(0b111111111);
+        assert_eq!(r.validate_request(), Err(T3p0Error::BoardNotEmptyAtStart));
+        let r = Request::new_data_request(false).set_board(0b1);
+        assert!(r.validate_request().is_err());
+    }
+
+    #[test]
+    fn validate_request_ninth_move() {
+        // The turn wraps to 0 on the ninth move, which fills the board.
+        let r = Request::new_data_request(false)
+            .set_board(0b111111111)
+            .set_o_marks(0b010101010)
+            .set_message_number(9)
+            .set_p2_turn(true);
+        assert_eq!(r.get_turn(), 0);
+        assert!(r.validate_request().is_ok());
+    }
+
+    #[test]
+    fn validate_request_first_move_pass() {
+        let r = Request::new_data_request(false)
+            .set_turn(1)
+            .set_message_number(1)
+            .set_p2_turn(true);
+        assert_eq!(r.validate_request(), Err(T3p0Error::FirstMoveNotOneMark));
+    }
+
+    #[test]
+    fn validate_request_first_move_too_many_marks() {
+        let r = Request::new_data_request(false)
+            .set_turn(1)
+            .set_message_number(1)
+            .set_p2_turn(true)
+            .set_board(0b11);
+        assert!(r.validate_request().is_err());
+    }
+
+    #[test]
+    fn validate_request_first_move_single_mark() {
+        for square in 0..9 {
+            let r = Request::new_data_request(false)
+                .set_turn(1)
+                .set_message_number(1)
+                .set_p2_turn(true)
+                .set_board(1 << square);
+            assert!(r.validate_request().is_ok());
+        }
+    }
+
     #[test]
     fn is_ok_response() {
         let r = Request::new_data_request(false);
-        assert_eq!(r.is_ok_response(), false);
+        assert!(!r.is_ok_response());
         let r = Request::new_data_request(true);
-        assert_eq!(r.is_ok_response(), true);
+        assert!(r.is_ok_response());
+    }
+
+    #[test]
+    fn set_turn() {
+        let r = Request::new_data_request(false).set_turn(5);
+        assert_eq!(r.get_turn(), 5);
+        assert_eq!(r, 5 << Bits::TurnOffset as u32);
+    }
+
+    #[test]
+    fn set_turn_bounds() {
+        // The maximum value fills the range and nothing else.
+        let r = Request(0).set_turn(15);
+        assert_eq!(r, 0b1111 << Bits::TurnOffset as u32);
+        // Bits that don't fit in the range are dropped instead of spilling into the message type.
+        let r = Request(0).set_turn(0b11111);
+        assert_eq!(r, 0b1111 << Bits::TurnOffset as u32);
+        assert!(!r.is_ok_response());
+        // Setting a smaller value clears the bits that were already set.
+        let r = r.set_turn(0);
+        assert_eq!(r, 0);
+    }
+
+    #[test]
+    fn set_turn_keeps_other_fields() {
+        let r = Request(u32::MAX).set_turn(0);
+        assert_eq!(r, u32::MAX ^ (0b1111 << Bits::TurnOffset as u32));
+        assert_eq!(r.get_message_number(), 31);
+        assert_eq!(r.get_board_state(), 511);
+        assert!(r.get_is_p2_turn());
+    }
+
+    #[test]
+    fn set_message_number() {
+        let r = Request::new_data_request(false).set_message_number(17);
+        assert_eq!(r.get_message_number(), 17);
+        assert_eq!(r, 17 << Bits::MessageNumber as u32);
+    }
+
+    #[test]
+    fn set_message_number_bounds() {
+        let r = Request(0).set_message_number(31);
+        assert_eq!(r, 0b11111 << Bits::MessageNumber as u32);
+        // The sixth bit would land on the player turn bit, it must be dropped.
+        let r = Request(0).set_message_number(0b111111);
+        assert_eq!(r, 0b11111 << Bits::MessageNumber as u32);
+        assert!(!r.get_is_p2_turn());
+        let r = r.set_message_number(0);
+        assert_eq!(r, 0);
+    }
+
+    #[test]
+    fn set_message_number_keeps_other_fields() {
+        let r = Request(u32::MAX).set_message_number(0);
+        assert_eq!(r, u32::MAX ^ (0b11111 << Bits::MessageNumber as u32));
+        assert_eq!(r.get_turn(), 15);
+        assert_eq!(r.get_board_state(), 511);
+        assert!(r.get_is_p2_turn());
+    }
+
+    #[test]
+    fn set_board() {
+        let r = Request::new_data_request(false).set_board(0b100000001);
+        assert_eq!(r.get_board_state(), 0b100000001);
+        assert_eq!(r, 0b100000001);
+    }
+
+    #[test]
+    fn set_board_bounds() {
+        let r = Request(0).set_board(511);
+        assert_eq!(r, 0b111111111);
+        // The tenth bit is in the unused range and must stay clear.
+        let r = Request(0).set_board(0b1111111111);
+        assert_eq!(r, 0b111111111);
+        let r = r.set_board(0);
+        assert_eq!(r, 0);
+    }
+
+    #[test]
+    fn set_board_keeps_other_fields() {
+        let r = Request(u32::MAX).set_board(0);
+        assert_eq!(r, u32::MAX ^ 0b111111111);
+        assert_eq!(r.get_turn(), 15);
+        assert_eq!(r.get_message_number(), 31);
+        assert!(r.get_is_p2_turn());
+    }
+
+    #[test]
+    fn set_p2_turn() {
+        let r = Request(0).set_p2_turn(true);
+        assert_eq!(r, 1 << Bits::P2Turn as u32);
+        assert!(r.get_is_p2_turn());
+        let r = r.set_p2_turn(false);
+        assert_eq!(r, 0);
+        // Setting the same value twice must not toggle it.
+        let r = Request(0).set_p2_turn(true).set_p2_turn(true);
+        assert!(r.get_is_p2_turn());
+    }
+
+    #[test]
+    fn set_p2_turn_keeps_other_fields() {
+        let r = Request(u32::MAX).set_p2_turn(false);
+        assert_eq!(r, u32::MAX ^ (1 << Bits::P2Turn as u32));
+        let r = r.set_p2_turn(true);
+        assert_eq!(r, u32::MAX);
+    }
+
+    #[test]
+    fn increment_turn_and_message_does_not_corrupt_turn() {
+        // A turn outside of the game range must be replaced without leaking into the message type bit.
+        let r = Request(0).set_turn(15).set_message_number(3);
+        let incremented = r.increment_turn_and_message().unwrap();
+        assert_eq!(incremented.get_turn(), 7);
+        assert_eq!(incremented.get_message_number(), 4);
+        assert!(!incremented.is_ok_response());
+        assert_eq!(incremented.0 >> Bits::MessageType as u32, 0);
+    }
+
+    #[test]
+    fn dry_run_bit() {
+        let r = Request::new_data_request(false)
+            .set_turn(1)
+            .set_message_number(1);
+        assert!(!r.is_dry_run());
+        let dry_run = r.set_dry_run(true);
+        assert!(dry_run.is_dry_run());
+        assert_eq!(dry_run.0, r.0 | 1 << Bits::DryRun as u32);
+        assert_eq!(dry_run.get_turn(), 1);
+        assert_eq!(dry_run.set_dry_run(false), r);
+    }
+
+    #[test]
+    fn second_side_bit() {
+        let r = Request::new_data_request(false);
+        assert!(!r.is_second_side());
+        let second = r.set_second_side(true);
+        assert!(second.is_second_side());
+        assert_eq!(second.0, 1 << Bits::SecondSide as u32);
+        assert!(!second.is_dry_run());
+        assert_eq!(second.set_second_side(false), r);
     }
 
     #[test]
     fn is_ok_format_issue() {
         let r = Request(1 << Bits::MessageType as u32 | 1);
-        assert_eq!(r.is_ok_response(), false);
+        assert!(!r.is_ok_response());
+    }
+}
+
+/// Differential tests of the `Request` codec against a reference model.
+/// The model stores every field in a plain struct and reads and writes the frame one bit at a
+/// time straight from the documented layout, so a shift or mask mistake in the codec shows up as
+/// a mismatch on some random frame.
+#[cfg(test)]
+mod conformance {
+    use super::*;
+
+    /// Bit positions written out from the protocol description, independent of `Bits` and `Ranges`.
+    const BOARD_BITS: std::ops::Range<u32> = 0..9;
+    const MESSAGE_NUMBER_BITS: std::ops::Range<u32> = 21..26;
+    const P2_TURN_BIT: u32 = 26;
+    const TURN_BITS: std::ops::Range<u32> = 27..31;
+    const MESSAGE_TYPE_BIT: u32 = 31;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Model {
+        board: [bool; 9],
+        message_number: u8,
+        is_p2_turn: bool,
+        turn: u8,
+        is_response: bool,
+        /// Bits 9-20, carried along untouched.
+        unused: u32,
+    }
+
+    fn bit(frame: u32, index: u32) -> bool {
+        frame & (1 << index) != 0
+    }
+
+    fn read_number(frame: u32, bits: std::ops::Range<u32>) -> u8 {
+        let mut value = 0;
+        for (place, index) in bits.enumerate() {
+            if bit(frame, index) {
+                value += 1 << place;
+            }
+        }
+        value
+    }
+
+    fn write_number(frame: &mut u32, bits: std::ops::Range<u32>, value: u8) {
+        for (place, index) in bits.enumerate() {
+            if value & (1 << place) != 0 {
+                *frame |= 1 << index;
+            }
+        }
+    }
+
+    impl Model {
+        fn decode(frame: u32) -> Self {
+            let mut board = [false; 9];
+            for index in BOARD_BITS {
+                board[index as usize] = bit(frame, index);
+            }
+            let mut unused = 0;
+            for index in 9..21 {
+                if bit(frame, index) {
+                    unused |= 1 << index;
+                }
+            }
+            Model {
+                board,
+                message_number: read_number(frame, MESSAGE_NUMBER_BITS),
+                is_p2_turn: bit(frame, P2_TURN_BIT),
+                turn: read_number(frame, TURN_BITS),
+                is_response: bit(frame, MESSAGE_TYPE_BIT),
+                unused,
+            }
+        }
+
+        fn encode(&self) -> u32 {
+            let mut frame = self.unused;
+            for (index, occupied) in self.board.iter().enumerate() {
+                if *occupied {
+                    frame |= 1 << index;
+                }
+            }
+            write_number(&mut frame, MESSAGE_NUMBER_BITS, self.message_number);
+            write_number(&mut frame, TURN_BITS, self.turn);
+            if self.is_p2_turn {
+                frame |= 1 << P2_TURN_BIT;
+            }
+            if self.is_response {
+                frame |= 1 << MESSAGE_TYPE_BIT;
+            }
+            frame
+        }
+
+        fn board_value(&self) -> u16 {
+            self.board
+                .iter()
+                .enumerate()
+                .filter(|(_, occupied)| **occupied)
+                .map(|(index, _)| 1 << index)
+                .sum()
+        }
+    }
+
+    /// xorshift64, enough to spread frames over the whole u32 range without a rand dependency.
+    struct Frames(u64);
+
+    impl Iterator for Frames {
+        type Item = u32;
+
+        fn next(&mut self) -> Option<u32> {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            Some((self.0 >> 32) as u32)
+        }
+    }
+
+    const ITERATIONS: usize = 100_000;
+
+    fn frames() -> impl Iterator<Item = u32> {
+        let edges = [
+            0,
+            u32::MAX,
+            1 << 31,
+            (1 << 31) - 1,
+            0x0155_5555,
+            0xAAAA_AAAA,
+        ];
+        edges
+            .into_iter()
+            .chain(Frames(0x9E37_79B9_7F4A_7C15).take(ITERATIONS))
+    }
+
+    #[test]
+    fn decode_matches_model() {
+        for frame in frames() {
+            let request = Request(frame);
+            let model = Model::decode(frame);
+            assert_eq!(
+                request.get_board_state(),
+                model.board_value(),
+                "{:#010x}",
+                frame
+            );
+            assert_eq!(request.get_turn(), model.turn, "{:#010x}", frame);
+            assert_eq!(
+                request.get_message_number(),
+                model.message_number,
+                "{:#010x}",
+                frame
+            );
+            assert_eq!(
+                request.get_is_p2_turn(),
+                model.is_p2_turn,
+                "{:#010x}",
+                frame
+            );
+            assert_eq!(
+                request.is_ok_response(),
+                model.is_response && frame == 1 << MESSAGE_TYPE_BIT,
+                "{:#010x}",
+                frame
+            );
+            assert_eq!(model.encode(), frame, "{:#010x}", frame);
+        }
+    }
+
+    #[test]
+    fn setters_match_model() {
+        let mut values = Frames(0xD1B5_4A32_D192_ED03);
+        for frame in frames() {
+            let value = values.next().unwrap();
+            let board = (value & 0x1FF) as u16;
+            let message_number = ((value >> 9) & 0x1F) as u8;
+            let turn = ((value >> 14) & 0xF) as u8;
+            let is_p2_turn = value & (1 << 18) != 0;
+
+            let request = Request(frame)
+                .set_board(board)
+                .set_message_number(message_number)
+                .set_turn(turn)
+                .set_p2_turn(is_p2_turn);
+
+            let mut model = Model::decode(frame);
+            for (index, square) in model.board.iter_mut().enumerate() {
+                *square = board & (1 << index) != 0;
+            }
+            model.message_number = message_number;
+            model.turn = turn;
+            model.is_p2_turn = is_p2_turn;
+
+            assert_eq!(request, model.encode(), "{:#010x}", frame);
+        }
+    }
+
+    #[test]
+    fn swap_player_matches_model() {
+        for frame in frames() {
+            let mut model = Model::decode(frame);
+            let o_marks = (model.unused >> 9) & 0x1ff;
+            let x_marks = u32::from(model.board_value()) & !o_marks;
+            model.unused = (model.unused & !(0x1ff << 9)) | (x_marks << 9);
+            model.is_p2_turn = !model.is_p2_turn;
+            assert_eq!(
+                Request(frame).swap_player(),
+                model.encode(),
+                "{:#010x}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn increment_turn_and_message_matches_model() {
+        for frame in frames() {
+            let mut model = Model::decode(frame);
+            let result = Request(frame).increment_turn_and_message();
+            if model.message_number + 1 >= 27 {
+                assert!(result.is_err(), "{:#010x}", frame);
+                continue;
+            }
+            model.turn = (model.turn + 1) % 9;
+            model.message_number += 1;
+            model.is_p2_turn = !model.is_p2_turn;
+            assert_eq!(result.unwrap(), model.encode(), "{:#010x}", frame);
+        }
     }
 }
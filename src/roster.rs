@@ -0,0 +1,237 @@
+// Who is playing a game.
+// A game has two sides and each side is a team of one or more players. Sides always alternate,
+// and the `Rotation` decides which member of a team makes that team's move, e.g. 2v2 games where
+// teammates take turns and advise each other in between.
+use crate::{error::T3p0Error, player::Player};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// Teammates take turns, so with two teams of two the order is A1 B1 A2 B2 A1 ...
+    #[default]
+    Alternate,
+    /// The first member of each team makes every move for it, the rest only advise.
+    Captain,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Roster {
+    teams: [Vec<Player>; 2],
+    rotation: Rotation,
+}
+
+pub trait RosterTrait {
+    fn pair(player_one: Player, player_two: Player) -> Self;
+    fn teams(
+        team_one: Vec<Player>,
+        team_two: Vec<Player>,
+        rotation: Rotation,
+    ) -> Result<Self, T3p0Error>
+    where
+        Self: Sized;
+    fn team(&self, side: usize) -> &[Player];
+    fn rotation(&self) -> Rotation;
+    fn contains(&self, player: &Player) -> bool;
+    fn side_of(&self, player: &Player) -> Option<usize>;
+    fn to_move(&self, message_number: u8) -> &Player;
+}
+
+impl RosterTrait for Roster {
+    /// A regular one on one game.
+    fn pair(player_one: Player, player_two: Player) -> Self {
+        Roster {
+            teams: [vec![player_one], vec![player_two]],
+            rotation: Rotation::default(),
+        }
+    }
+
+    /// A game between two teams.
+    ///
+    /// # Arguments
+    ///
+    /// * `team_one` - The first side, in the order its members move.
+    /// * `team_two` - The second side, in the order its members move.
+    /// * `rotation` - Which member of a team makes each of its moves.
+    ///
+    /// # Errors
+    ///
+    /// * `T3p0Error::EmptyTeam` - If a team is empty.
+    /// * `T3p0Error::DuplicatePlayer` - If a player is listed more than once.
+    fn teams(
+        team_one: Vec<Player>,
+        team_two: Vec<Player>,
+        rotation: Rotation,
+    ) -> Result<Self, T3p0Error> {
+        if team_one.is_empty() || team_two.is_empty() {
+            return Err(T3p0Error::EmptyTeam);
+        }
+        let everyone: Vec<&Player> = team_one.iter().chain(&team_two).collect();
+        for (i, player) in everyone.iter().enumerate() {
+            if everyone[i + 1..].contains(player) {
+                return Err(T3p0Error::DuplicatePlayer);
+            }
+        }
+        Ok(Roster {
+            teams: [team_one, team_two],
+            rotation,
+        })
+    }
+
+    /// The members of a side, 0 for the first side and 1 for the second.
+    ///
+    /// # Panics
+    ///
+    /// * If `side` is greater than 1.
+    fn team(&self, side: usize) -> &[Player] {
+        &self.teams[side]
+    }
+
+    fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    fn contains(&self, player: &Player) -> bool {
+        self.side_of(player).is_some()
+    }
+
+    /// Finds which side a player is on.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<usize>` - 0 or 1, or `None` if the player isn't in the game.
+    fn side_of(&self, player: &Player) -> Option<usize> {
+        self.teams.iter().position(|team| team.contains(player))
+    }
+
+    /// Works out who submits the state with the given message number. Odd message numbers
+    /// belong to the first side, which plays X, see `mark_for`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_number` - The message number of the state being submitted.
+    ///
+    /// # Returns
+    ///
+    /// * `&Player` - The player whose move it is.
+    fn to_move(&self, message_number: u8) -> &Player {
+        // Count moves from message 1, so each side's moves are numbered 0, 1, 2 ... in turn.
+        let moves_before = message_number.wrapping_sub(1);
+        let team = &self.teams[usize::from(moves_before % 2)];
+        let member = match self.rotation {
+            Rotation::Alternate => usize::from(moves_before / 2) % team.len(),
+            Rotation::Captain => 0,
+        };
+        &team[member]
+    }
+}
+
+impl From<[Player; 2]> for Roster {
+    fn from([player_one, player_two]: [Player; 2]) -> Self {
+        Roster::pair(player_one, player_two)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::PlayerTrait;
+
+    fn player(id: u8) -> Player {
+        Player::from_bytes(&[id; 16])
+    }
+
+    #[test]
+    fn pair_alternates_sides() {
+        let roster = Roster::pair(player(1), player(2));
+        assert_eq!(roster.to_move(1), &player(1));
+        assert_eq!(roster.to_move(2), &player(2));
+        assert_eq!(roster.to_move(3), &player(1));
+        assert_eq!(roster.side_of(&player(2)), Some(1));
+        assert!(!roster.contains(&player(3)));
+    }
+
+    #[test]
+    fn teammates_alternate() {
+        let roster = Roster::teams(
+            vec![player(1), player(2)],
+            vec![player(3), player(4)],
+            Rotation::Alternate,
+        )
+        .unwrap();
+        let order: Vec<_> = (1..7).map(|m| roster.to_move(m).clone()).collect();
+        assert_eq!(
+            order,
+            [
+                player(1),
+                player(3),
+                player(2),
+                player(4),
+                player(1),
+                player(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn captain_makes_every_move() {
+        let roster = Roster::teams(
+            vec![player(1), player(2)],
+            vec![player(3)],
+            Rotation::Captain,
+        )
+        .unwrap();
+        assert_eq!(roster.to_move(1), &player(1));
+        assert_eq!(roster.to_move(3), &player(1));
+        assert_eq!(roster.to_move(2), &player(3));
+        assert!(roster.contains(&player(2)));
+    }
+
+    #[test]
+    fn rejects_empty_teams_and_duplicates() {
+        assert_eq!(
+            Roster::teams(vec![], vec![player(1)], Rotation::Alternate),
+            Err(T3p0Error::EmptyTeam)
+        );
+        assert_eq!(
+            Roster::teams(
+                vec![player(1), player(2)],
+                vec![player(2)],
+                Rotation::Alternate
+            ),
+            Err(T3p0Error::DuplicatePlayer)
+        );
+        assert_eq!(
+            Roster::teams(
+                vec![player(1), player(1)],
+                vec![player(2)],
+                Rotation::Captain
+            ),
+            Err(T3p0Error::DuplicatePlayer)
+        );
+    }
+
+    #[test]
+    fn to_move_at_maximum_message_number() {
+        let roster = Roster::teams(
+            vec![player(1), player(2)],
+            vec![player(3)],
+            Rotation::Alternate,
+        )
+        .unwrap();
+        assert_eq!(roster.to_move(u8::MAX), &player(2));
+    }
+
+    #[test]
+    fn to_move_agrees_with_mark_for() {
+        use crate::game_state::{mark_for, X_MARK};
+
+        let roster = Roster::pair(player(1), player(2));
+        for message_number in 1..=9 {
+            let side = usize::from(mark_for(message_number) != X_MARK);
+            assert_eq!(
+                roster.side_of(roster.to_move(message_number)),
+                Some(side),
+                "message {message_number}"
+            );
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// How the server schedules player connections.
+// The default is tokio's work stealing scheduler. Thread-per-core instead runs one single threaded
+// runtime per core and pins each connection to one of them by a hash of its peer address, so a
+// connection's tasks never migrate between threads.
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuntimeLayout {
+    /// Every connection runs on the shared multithreaded runtime.
+    #[default]
+    MultiThread,
+    /// Connections are sharded across one current-thread runtime per core.
+    ThreadPerCore,
+}
+
+impl RuntimeLayout {
+    /// Looks up a layout by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Either `multi-thread` or `thread-per-core`.
+    ///
+    /// # Errors
+    ///
+    /// * `&'static str` - If the name isn't a known layout.
+    pub fn from_name(name: &str) -> Result<Self, &'static str> {
+        match name {
+            "multi-thread" => Ok(RuntimeLayout::MultiThread),
+            "thread-per-core" => Ok(RuntimeLayout::ThreadPerCore),
+            _ => Err("Unknown runtime layout, expected multi-thread or thread-per-core."),
+        }
+    }
+}
+
+/// Picks the shard a connection runs on. The same peer always lands on the same shard.
+///
+/// # Arguments
+///
+/// * `peer` - The connection's remote address.
+/// * `shards` - How many shards there are, at least 1.
+///
+/// # Returns
+///
+/// * `usize` - The shard's index, less than `shards`.
+pub fn shard_for(peer: &SocketAddr, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    peer.hash(&mut hasher);
+    (hasher.finish() % shards.max(1) as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name() {
+        assert_eq!(
+            RuntimeLayout::from_name("multi-thread"),
+            Ok(RuntimeLayout::MultiThread)
+        );
+        assert_eq!(
+            RuntimeLayout::from_name("thread-per-core"),
+            Ok(RuntimeLayout::ThreadPerCore)
+        );
+        assert!(RuntimeLayout::from_name("single").is_err());
+    }
+
+    #[test]
+    fn shard_is_stable_and_in_range() {
+        let peer: SocketAddr = "192.0.2.1:50000".parse().unwrap();
+        assert_eq!(shard_for(&peer, 4), shard_for(&peer, 4));
+        assert_eq!(shard_for(&peer, 1), 0);
+        assert_eq!(shard_for(&peer, 0), 0);
+        for port in 0..100 {
+            let peer = SocketAddr::from(([192, 0, 2, 1], port));
+            assert!(shard_for(&peer, 3) < 3);
+        }
+    }
+
+    #[test]
+    fn peers_spread_across_shards() {
+        let mut used = [false; 4];
+        for port in 50000..50100 {
+            used[shard_for(&SocketAddr::from(([192, 0, 2, 1], port)), 4)] = true;
+        }
+        assert_eq!(used, [true; 4]);
+    }
+}
@@ -0,0 +1,240 @@
+// A bounded outbound queue for one connection.
+// Frames wait here until the connection's writer sends them, so a client that stops reading can
+// only ever hold `capacity` frames of server memory. What happens when the queue is full is
+// decided by the `OverflowPolicy`.
+use std::collections::VecDeque;
+
+use crate::error::T3p0Error;
+
+/// How important a queued frame is to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameClass {
+    /// Frames the client can do without, like keep-alive pings. A newer one replaces them.
+    Status,
+    /// Answers to the client's frames: game states, echoes and NACKs. These are never dropped.
+    Reply,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Make room by dropping the oldest status frame. If only replies are queued a new status
+    /// frame is dropped instead, and a new reply closes the connection.
+    #[default]
+    DropOldestStatus,
+    /// Close the connection as soon as the queue is full.
+    Disconnect,
+}
+
+impl OverflowPolicy {
+    /// Looks up a policy by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Either `drop-oldest-status` or `disconnect`.
+    ///
+    /// # Errors
+    ///
+    /// * `&'static str` - If the name isn't a known policy.
+    pub fn from_name(name: &str) -> Result<Self, &'static str> {
+        match name {
+            "drop-oldest-status" => Ok(OverflowPolicy::DropOldestStatus),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            _ => Err("Unknown overflow policy, expected drop-oldest-status or disconnect."),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SendQueue {
+    frames: VecDeque<(FrameClass, Vec<u8>)>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: u64,
+    closed: bool,
+}
+
+pub trait SendQueueTrait {
+    fn new(capacity: usize, policy: OverflowPolicy) -> Self;
+    fn push(&mut self, class: FrameClass, frame: Vec<u8>) -> Result<(), T3p0Error>;
+    fn pop(&mut self) -> Option<Vec<u8>>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn dropped(&self) -> u64;
+    fn close(&mut self);
+    fn is_closed(&self) -> bool;
+}
+
+impl SendQueueTrait for SendQueue {
+    /// Creates an empty queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The most frames that can wait to be sent.
+    /// * `policy` - What to do with a frame that doesn't fit.
+    fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        SendQueue {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+            dropped: 0,
+            closed: false,
+        }
+    }
+
+    /// Queues a frame to be sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `class` - Whether the frame may be dropped to make room.
+    /// * `frame` - The bytes to send.
+    ///
+    /// # Errors
+    ///
+    /// * `T3p0Error::ConnectionClosed` - If the queue is closed.
+    /// * `T3p0Error::SendQueueFull` - If the queue is full and the policy is to disconnect.
+    /// * `T3p0Error::UnreadReplies` - If the queue is full of replies, which are never dropped.
+    ///
+    /// The connection should be closed on any of these.
+    fn push(&mut self, class: FrameClass, frame: Vec<u8>) -> Result<(), T3p0Error> {
+        if self.closed {
+            return Err(T3p0Error::ConnectionClosed);
+        }
+        if self.frames.len() >= self.capacity {
+            if self.policy == OverflowPolicy::Disconnect {
+                return Err(T3p0Error::SendQueueFull);
+            }
+            let oldest_status = self
+                .frames
+                .iter()
+                .position(|(queued, _)| *queued == FrameClass::Status);
+            match (oldest_status, class) {
+                (Some(index), _) => {
+                    self.frames.remove(index);
+                }
+                (None, FrameClass::Status) => {
+                    self.dropped += 1;
+                    return Ok(());
+                }
+                (None, FrameClass::Reply) => return Err(T3p0Error::UnreadReplies),
+            }
+            self.dropped += 1;
+        }
+        self.frames.push_back((class, frame));
+        Ok(())
+    }
+
+    /// Takes the next frame to send.
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        self.frames.pop_front().map(|(_, frame)| frame)
+    }
+
+    fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The number of status frames dropped to make room.
+    fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Stops accepting frames. Frames already queued can still be popped.
+    fn close(&mut self) {
+        self.closed = true;
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_come_out_in_order() {
+        let mut queue = SendQueue::new(4, OverflowPolicy::DropOldestStatus);
+        queue.push(FrameClass::Reply, vec![1]).unwrap();
+        queue.push(FrameClass::Status, vec![2]).unwrap();
+        queue.push(FrameClass::Reply, vec![3]).unwrap();
+        assert_eq!(queue.pop(), Some(vec![1]));
+        assert_eq!(queue.pop(), Some(vec![2]));
+        assert_eq!(queue.pop(), Some(vec![3]));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn full_queue_drops_oldest_status() {
+        let mut queue = SendQueue::new(3, OverflowPolicy::DropOldestStatus);
+        queue.push(FrameClass::Reply, vec![1]).unwrap();
+        queue.push(FrameClass::Status, vec![2]).unwrap();
+        queue.push(FrameClass::Status, vec![3]).unwrap();
+        queue.push(FrameClass::Reply, vec![4]).unwrap();
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop(), Some(vec![1]));
+        assert_eq!(queue.pop(), Some(vec![3]));
+        assert_eq!(queue.pop(), Some(vec![4]));
+    }
+
+    #[test]
+    fn full_of_replies_drops_new_status() {
+        let mut queue = SendQueue::new(1, OverflowPolicy::DropOldestStatus);
+        queue.push(FrameClass::Reply, vec![1]).unwrap();
+        assert!(queue.push(FrameClass::Status, vec![2]).is_ok());
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.pop(), Some(vec![1]));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn replies_are_never_dropped() {
+        let mut queue = SendQueue::new(1, OverflowPolicy::DropOldestStatus);
+        queue.push(FrameClass::Reply, vec![1]).unwrap();
+        assert_eq!(
+            queue.push(FrameClass::Reply, vec![2]),
+            Err(T3p0Error::UnreadReplies)
+        );
+        assert_eq!(queue.pop(), Some(vec![1]));
+    }
+
+    #[test]
+    fn disconnect_policy() {
+        let mut queue = SendQueue::new(1, OverflowPolicy::Disconnect);
+        queue.push(FrameClass::Status, vec![1]).unwrap();
+        assert_eq!(
+            queue.push(FrameClass::Status, vec![2]),
+            Err(T3p0Error::SendQueueFull)
+        );
+    }
+
+    #[test]
+    fn closed_queue_still_drains() {
+        let mut queue = SendQueue::new(2, OverflowPolicy::DropOldestStatus);
+        queue.push(FrameClass::Reply, vec![1]).unwrap();
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(
+            queue.push(FrameClass::Reply, vec![2]),
+            Err(T3p0Error::ConnectionClosed)
+        );
+        assert_eq!(queue.pop(), Some(vec![1]));
+    }
+
+    #[test]
+    fn from_name() {
+        assert_eq!(
+            OverflowPolicy::from_name("drop-oldest-status"),
+            Ok(OverflowPolicy::DropOldestStatus)
+        );
+        assert_eq!(
+            OverflowPolicy::from_name("disconnect"),
+            Ok(OverflowPolicy::Disconnect)
+        );
+        assert!(OverflowPolicy::from_name("block").is_err());
+    }
+}
@@ -0,0 +1,1424 @@
+// The game server: player connections, the state actor that owns every game, the observer port,
+// LAN discovery and (with the `status-page` feature) the status page.
+// The `tic_tac_toe_protocol` binary is a thin wrapper that reads its configuration from the
+// environment. Anything else can embed a `Server` in its own tokio runtime the same way.
+use socket2::{SockRef, TcpKeepalive};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpSocket, TcpStream, UdpSocket},
+    sync::{mpsc, watch, Notify},
+    task::JoinHandle,
+};
+use uuid::Uuid;
+
+use crate::{
+    connection::{Action, ConnectionCore, ConnectionCoreTrait},
+    discovery::{
+        Announcement, AnnouncementTrait, DISCOVERY_GROUP, DISCOVERY_PORT, DISCOVERY_QUERY,
+    },
+    error::T3p0Error,
+    event_log::{EventLog, EventLogTrait, LogEvent, LogEventKind},
+    game_state::{GameMode, GameState, GameStateTrait, Privacy},
+    keep_alive::KeepAliveConfig,
+    mailbox::{mailbox, Lane, Mailbox, MailboxSender},
+    match_options::{MatchOptions, MatchOptionsTrait},
+    matchmaking::{new_match, Matchmaker, MatchmakerTrait},
+    nack::NackTrait,
+    observer::render_board,
+    player::{Player, PlayerTrait},
+    quarantine::{Quarantine, QuarantineRecord, QuarantineRecordTrait, QuarantineTrait},
+    rate_limit::{TokenBucket, TokenBucketTrait},
+    request::{DataRequest, Request},
+    roster::RosterTrait,
+    runtime_layout::{shard_for, RuntimeLayout},
+    send_queue::{FrameClass, OverflowPolicy, SendQueue, SendQueueTrait},
+    stats::{Stats, StatsTrait},
+};
+
+#[cfg(feature = "status-page")]
+use crate::status_page::{StatusReport, StatusReportTrait};
+
+/// How often an observer's board is checked for changes.
+const OBSERVER_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// How long an observer has to type the player id.
+const OBSERVER_PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long `/readyz` waits for the state actor before reporting the server as not ready.
+#[cfg(feature = "status-page")]
+const READINESS_DEADLINE: Duration = Duration::from_secs(1);
+/// How many frames may wait to be sent to one client.
+const SEND_QUEUE_CAPACITY: usize = 64;
+/// The size the quarantine log may reach before it is rotated.
+const QUARANTINE_LOG_MAX_BYTES: u64 = 1024 * 1024;
+/// The sustained number of frames per second a connection may send after the handshake.
+const FRAMES_PER_SECOND: u32 = 20;
+/// The largest burst of frames a connection may send at once.
+const FRAME_BURST: u32 = 40;
+/// A connection that has this many frames in a row dropped by the rate limiter is disconnected.
+const MAX_RATE_LIMITED_FRAMES: u32 = 20;
+/// How many move path log events may wait for the logger thread before new ones are dropped.
+const EVENT_LOG_CAPACITY: usize = 4096;
+/// State actor commands that take longer than this are reported as slow.
+const SLOW_COMMAND_THRESHOLD: Duration = Duration::from_millis(50);
+/// How often a draining server checks whether its last connection has closed.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long an accept loop waits after a failed accept, so running out of file descriptors
+/// doesn't turn it into a busy loop.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+/// How long a stopping server waits for the games in progress before closing their connections.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the server listens and how it treats connections.
+/// The default is what the standalone binary runs with when no environment variables are set.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Where players connect.
+    pub addr: SocketAddr,
+    /// Opens a read-only plain text port here for watching a game with telnet or netcat.
+    pub observer_addr: Option<SocketAddr>,
+    /// Address for the HTTP status page.
+    #[cfg(feature = "status-page")]
+    pub status_addr: SocketAddr,
+    /// Writes every rejected frame to a quarantine log at this path.
+    pub quarantine_log: Option<PathBuf>,
+    /// Answers LAN discovery queries, announcing the server under this name.
+    pub discovery_name: Option<String>,
+    pub keep_alive: KeepAliveConfig,
+    /// What happens when a client's send queue is full.
+    pub overflow_policy: OverflowPolicy,
+    /// How connections are scheduled.
+    pub runtime_layout: RuntimeLayout,
+    /// How long `run` waits for connections to close after `shutdown` before closing them itself.
+    pub drain_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: SocketAddr::from(([127, 0, 0, 1], 8000)),
+            observer_addr: None,
+            #[cfg(feature = "status-page")]
+            status_addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            quarantine_log: None,
+            discovery_name: None,
+            keep_alive: KeepAliveConfig::default(),
+            overflow_policy: OverflowPolicy::default(),
+            runtime_layout: RuntimeLayout::default(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+}
+
+/// A bound server. Nothing is accepted until `run` is called.
+#[derive(Debug)]
+pub struct Server {
+    config: ServerConfig,
+    local_addr: SocketAddr,
+    observer_addr: Option<SocketAddr>,
+    /// Taken by `run`, so a server only runs once.
+    listeners: StdMutex<Option<Listeners>>,
+    /// Set by `shutdown`.
+    stopping: watch::Sender<bool>,
+}
+
+#[derive(Debug)]
+struct Listeners {
+    players: TcpListener,
+    observers: Option<TcpListener>,
+    #[cfg(feature = "status-page")]
+    status: TcpListener,
+    discovery: Option<(UdpSocket, Announcement)>,
+}
+
+impl Server {
+    /// Binds every socket the server listens on. Binding port 0 picks a free port, see
+    /// `local_addr`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Where to listen and how to treat connections.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If an address can't be bound.
+    pub async fn bind(config: ServerConfig) -> io::Result<Server> {
+        let players = bind_listener(config.addr)?;
+        let observers = match config.observer_addr {
+            Some(addr) => Some(TcpListener::bind(addr).await?),
+            None => None,
+        };
+        #[cfg(feature = "status-page")]
+        let status = TcpListener::bind(config.status_addr).await?;
+        let local_addr = players.local_addr()?;
+        let discovery = match &config.discovery_name {
+            Some(name) => {
+                let announcement = Announcement::new(name, local_addr.port());
+                let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).await?;
+                socket.join_multicast_v4(DISCOVERY_GROUP, Ipv4Addr::UNSPECIFIED)?;
+                Some((socket, announcement))
+            }
+            None => None,
+        };
+        Ok(Server {
+            local_addr,
+            observer_addr: observers
+                .as_ref()
+                .map(TcpListener::local_addr)
+                .transpose()?,
+            listeners: StdMutex::new(Some(Listeners {
+                players,
+                observers,
+                #[cfg(feature = "status-page")]
+                status,
+                discovery,
+            })),
+            stopping: watch::channel(false).0,
+            config,
+        })
+    }
+
+    /// The address players connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The address of the observer port, if it's open.
+    pub fn observer_addr(&self) -> Option<SocketAddr> {
+        self.observer_addr
+    }
+
+    /// Serves players until `shutdown` is called, then stops accepting and waits for the games
+    /// in progress to finish. Connections still open after the drain timeout are closed. The
+    /// observer port, discovery and status page stop with it.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If the server threads can't be started, or the server has already run.
+    pub async fn run(&self) -> io::Result<()> {
+        let Some(listeners) = lock(&self.listeners).take() else {
+            return Err(io::Error::other("The server has already run."));
+        };
+        let ServerConfig {
+            keep_alive,
+            overflow_policy,
+            runtime_layout,
+            drain_timeout,
+            ..
+        } = self.config;
+        let (tx, rx) = mailbox::<GameRequest>(32);
+        let stats = Arc::new(Stats::new());
+        let quarantine = self
+            .config
+            .quarantine_log
+            .clone()
+            .map(|path| Arc::new(Quarantine::new(path, QUARANTINE_LOG_MAX_BYTES)));
+        // The observer port, discovery and status page, stopped once the players have drained.
+        let mut background: Vec<JoinHandle<()>> = Vec::new();
+
+        // Move path events are formatted and printed here so connections never wait on stdout.
+        let (event_log, log_events) = EventLog::new(EVENT_LOG_CAPACITY);
+        std::thread::Builder::new()
+            .name("event-logger".to_string())
+            .spawn(move || {
+                for event in log_events {
+                    println!("{}", event);
+                }
+            })?;
+
+        tokio::spawn(run_state_actor(rx, stats.clone()));
+
+        if let Some(observer_listener) = listeners.observers {
+            let observer_tx = tx.clone();
+            background.push(tokio::spawn(async move {
+                loop {
+                    let socket = match observer_listener.accept().await {
+                        Ok((socket, _)) => socket,
+                        Err(e) => {
+                            eprintln!("Observer accept error: {:?}", e);
+                            tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    let tx_clone = observer_tx.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) =
+                            handle_observer(socket, tx_clone, keep_alive.write_timeout).await
+                        {
+                            eprintln!("Observer error: {:?}", e);
+                        }
+                    });
+                }
+            }));
+        }
+
+        if let Some((discovery_socket, announcement)) = listeners.discovery {
+            background.push(tokio::spawn(answer_discovery_queries(
+                discovery_socket,
+                announcement,
+            )));
+        }
+
+        // Cleared when shutdown starts so `/readyz` sends new traffic elsewhere while draining.
+        let accepting = Arc::new(AtomicBool::new(true));
+
+        let matchmaker = Arc::new(StdMutex::new(Matchmaker::new()));
+
+        #[cfg(feature = "status-page")]
+        {
+            let status_listener = listeners.status;
+            let status_stats = stats.clone();
+            let status_matchmaker = matchmaker.clone();
+            let status_tx = tx.clone();
+            let status_accepting = accepting.clone();
+            let started = Instant::now();
+            background.push(tokio::spawn(async move {
+                loop {
+                    let socket = match status_listener.accept().await {
+                        Ok((socket, _)) => socket,
+                        Err(e) => {
+                            eprintln!("Status page accept error: {:?}", e);
+                            tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    let report = StatusReport::new(
+                        started.elapsed(),
+                        status_stats.snapshot(),
+                        lock(&status_matchmaker).len(),
+                        status_stats.recent_results(),
+                    );
+                    let tx_clone = status_tx.clone();
+                    let accepting = status_accepting.load(Ordering::Relaxed);
+                    tokio::spawn(async move {
+                        if let Err(e) =
+                            handle_status_request(socket, report, tx_clone, accepting, keep_alive)
+                                .await
+                        {
+                            eprintln!("Status page error: {:?}", e);
+                        }
+                    });
+                }
+            }));
+        }
+
+        // Set once the drain timeout has passed, telling the remaining connections to close.
+        let (closing, closing_rx) = watch::channel(false);
+        let context = ConnectionContext {
+            tx,
+            stats: stats.clone(),
+            quarantine,
+            event_log,
+            keep_alive,
+            overflow_policy,
+            peers: Arc::new(StdMutex::new(HashMap::new())),
+            matchmaker,
+            closing: closing_rx,
+        };
+        let shards = match runtime_layout {
+            RuntimeLayout::MultiThread => None,
+            RuntimeLayout::ThreadPerCore => Some(start_shards(&context)?),
+        };
+
+        let listener = listeners.players;
+        let mut stopping = self.stopping.subscribe();
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = stopping.wait_for(|&stopping| stopping) => break,
+            };
+            let socket = match accepted {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    eprintln!("Accept error: {:?}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                    continue;
+                }
+            };
+            stats.connection_opened();
+            if let Err(e) = set_tcp_keepalive(&socket, &keep_alive) {
+                eprintln!("Failed to set TCP keepalive: {:?}", e);
+            }
+            match &shards {
+                None => {
+                    tokio::spawn(serve_connection(socket, context.clone()));
+                }
+                Some(shards) => {
+                    if let Err(e) = send_to_shard(shards, socket) {
+                        eprintln!("Failed to hand connection to a shard: {:?}", e);
+                        stats.connection_closed();
+                    }
+                }
+            }
+        }
+
+        // Stop accepting so a replacement process bound to the same port picks up new connections,
+        // then let the games already in progress finish.
+        accepting.store(false, Ordering::Relaxed);
+        drop(listener);
+        println!(
+            "Draining {} connection(s)",
+            stats.snapshot().connections_active
+        );
+        if tokio::time::timeout(drain_timeout, drained(&stats))
+            .await
+            .is_err()
+        {
+            println!(
+                "Closing {} connection(s) still open after {:?}",
+                stats.snapshot().connections_active,
+                drain_timeout
+            );
+            closing.send_replace(true);
+            drained(&stats).await;
+        }
+        for task in background {
+            task.abort();
+        }
+        Ok(())
+    }
+
+    /// Asks a running server to stop. `run` returns once the games in progress have finished, or
+    /// their connections have been closed after the drain timeout.
+    /// Calling this before `run` makes `run` drain and return straight away.
+    pub fn shutdown(&self) {
+        self.stopping.send_replace(true);
+    }
+}
+
+#[derive(Debug)]
+enum GameRequest {
+    GetState {
+        player_id: Player,
+        response: mpsc::Sender<Option<GameState>>,
+    },
+    /// Stores the state under every player in its roster.
+    UpdateState {
+        player_id: Player,
+        new_state: GameState,
+    },
+    /// Removes the player's game from under every player in its roster.
+    EndGame { player_id: Player },
+}
+
+/// Owns every stored game. Connections read and update games only through `GameRequest`s, so the
+/// map never needs a lock.
+async fn run_state_actor(mut rx: Mailbox<GameRequest>, stats: Arc<Stats>) {
+    let mut state = HashMap::<Player, GameState>::new();
+    while let Some(request) = rx.recv().await {
+        // Watchdog: time every command so pathological states show up before they stall games.
+        let started = Instant::now();
+        let (command, command_player) = match &request {
+            GameRequest::GetState { player_id, .. } => ("GetState", player_id.clone()),
+            GameRequest::UpdateState { player_id, .. } => ("UpdateState", player_id.clone()),
+            GameRequest::EndGame { player_id } => ("EndGame", player_id.clone()),
+        };
+
+        match request {
+            GameRequest::GetState {
+                player_id,
+                response,
+            } => {
+                let game_state = state.get(&player_id).cloned();
+                let _ = response.send(game_state).await;
+            }
+            GameRequest::UpdateState {
+                player_id,
+                new_state,
+            } => {
+                if !state.contains_key(&player_id) {
+                    stats.game_started();
+                }
+                for member in members(&player_id, &new_state) {
+                    state.insert(member, new_state.clone());
+                }
+            }
+            GameRequest::EndGame { player_id } => {
+                if let Some(game) = state.remove(&player_id) {
+                    for member in members(&player_id, &game) {
+                        state.remove(&member);
+                    }
+                    stats.game_finished(&game);
+                    stats.game_ended();
+                }
+            }
+        }
+
+        let elapsed = started.elapsed();
+        if elapsed >= SLOW_COMMAND_THRESHOLD {
+            stats.slow_command();
+            eprintln!(
+                "Slow command: {} for {:?} took {:?}",
+                command, command_player, elapsed
+            );
+        }
+    }
+}
+
+/// The players a game is stored under: its roster, or the player who sent it if it has none.
+fn members(player: &Player, game_state: &GameState) -> Vec<Player> {
+    match game_state.get_roster() {
+        Some(roster) => roster
+            .team(0)
+            .iter()
+            .chain(roster.team(1))
+            .cloned()
+            .collect(),
+        None => vec![player.clone()],
+    }
+}
+
+/// The way to reach a connected player, so another connection can relay a move to them.
+#[derive(Clone)]
+struct Peer {
+    queue: Arc<StdMutex<SendQueue>>,
+    queued: Arc<Notify>,
+    /// The privacy the player asked for in the handshake, used when they are matched.
+    privacy: Privacy,
+}
+
+impl Peer {
+    /// Queues a frame for the player's writer task.
+    ///
+    /// # Errors
+    ///
+    /// * `T3p0Error` - If the queue is closed or full, see `SendQueue::push`.
+    fn push(&self, class: FrameClass, frame: &[u8]) -> Result<(), T3p0Error> {
+        lock(&self.queue).push(class, frame.to_vec())?;
+        self.queued.notify_one();
+        Ok(())
+    }
+
+    /// Sends another connection's frame to the player. If it can't be queued the player's queue
+    /// is closed, since a player who misses a move is out of sync with their game.
+    fn relay(&self, frame: &[u8]) {
+        if self.push(FrameClass::Reply, frame).is_err() {
+            lock(&self.queue).close();
+            self.queued.notify_one();
+        }
+    }
+}
+
+/// Everything a player connection shares with the rest of the server.
+/// Cheap to clone, every connection and every shard gets its own copy.
+#[derive(Clone)]
+struct ConnectionContext {
+    tx: MailboxSender<GameRequest>,
+    stats: Arc<Stats>,
+    quarantine: Option<Arc<Quarantine>>,
+    event_log: EventLog,
+    keep_alive: KeepAliveConfig,
+    overflow_policy: OverflowPolicy,
+    /// Every connected player, by player id.
+    peers: Arc<StdMutex<HashMap<Player, Peer>>>,
+    /// Players waiting for an opponent.
+    matchmaker: Arc<StdMutex<Matchmaker>>,
+    /// Becomes true when a stopping server gives up waiting for connections to close.
+    closing: watch::Receiver<bool>,
+}
+
+/// Resolves once every player connection has closed.
+async fn drained(stats: &Stats) {
+    while stats.snapshot().connections_active > 0 {
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+}
+
+/// Starts one current-thread runtime per core, each on its own thread, running the connections
+/// sent to it. The accept loop stays on the main runtime along with the state actor, observers
+/// and status page.
+///
+/// # Returns
+///
+/// * `Vec<mpsc::UnboundedSender<std::net::TcpStream>>` - One sender per shard. Unbounded so a
+///   busy shard never stalls the accept loop.
+fn start_shards(
+    context: &ConnectionContext,
+) -> std::io::Result<Vec<mpsc::UnboundedSender<std::net::TcpStream>>> {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    (0..cores)
+        .map(|shard| {
+            let (sender, mut receiver) = mpsc::unbounded_channel::<std::net::TcpStream>();
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            let context = context.clone();
+            std::thread::Builder::new()
+                .name(format!("shard-{}", shard))
+                .spawn(move || {
+                    runtime.block_on(async move {
+                        while let Some(socket) = receiver.recv().await {
+                            match TcpStream::from_std(socket) {
+                                Ok(socket) => {
+                                    tokio::spawn(serve_connection(socket, context.clone()));
+                                }
+                                Err(e) => {
+                                    eprintln!("Shard {} error: {:?}", shard, e);
+                                    context.stats.connection_closed();
+                                }
+                            }
+                        }
+                    })
+                })?;
+            Ok(sender)
+        })
+        .collect()
+}
+
+/// Moves an accepted connection to the shard picked by its peer address. The socket is
+/// deregistered from the main runtime and registered again on the shard's.
+fn send_to_shard(
+    shards: &[mpsc::UnboundedSender<std::net::TcpStream>],
+    socket: TcpStream,
+) -> std::io::Result<()> {
+    let shard = socket
+        .peer_addr()
+        .map_or(0, |peer| shard_for(&peer, shards.len()));
+    shards[shard]
+        .send(socket.into_std()?)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "The shard has stopped."))
+}
+
+/// Runs a player connection to the end.
+async fn serve_connection(socket: TcpStream, context: ConnectionContext) {
+    if let Err(e) = handle_connection(socket, &context).await {
+        eprintln!("Error: {:?}", e);
+    }
+    context.stats.connection_closed();
+}
+
+/// Answers one HTTP request on the status listener and closes the connection.
+///
+/// * `GET /healthz` - Liveness, 200 whenever the process can answer at all.
+/// * `GET /readyz` - Readiness, 200 if the server is accepting players and the state actor
+///   answers within `READINESS_DEADLINE`, 503 otherwise. The game and observer listeners are
+///   bound before this listener, so they don't need checking.
+/// * `GET /status.json` - The status report as JSON.
+/// * Any other `GET` - The status report as HTML.
+#[cfg(feature = "status-page")]
+async fn handle_status_request(
+    socket: TcpStream,
+    report: StatusReport,
+    tx: MailboxSender<GameRequest>,
+    accepting: bool,
+    keep_alive: KeepAliveConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let Some(request_line) = with_deadline(keep_alive.handshake_timeout, lines.next_line()).await?
+    else {
+        return Ok(());
+    };
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/healthz")) => ("200 OK", "text/plain", "ok\n".to_string()),
+        (Some("GET"), Some("/readyz")) => match check_readiness(&tx, accepting).await {
+            Ok(()) => ("200 OK", "text/plain", "ready\n".to_string()),
+            Err(reason) => (
+                "503 Service Unavailable",
+                "text/plain",
+                format!("{}\n", reason),
+            ),
+        },
+        (Some("GET"), Some("/status.json")) => ("200 OK", "application/json", report.to_json()),
+        (Some("GET"), Some(_)) => ("200 OK", "text/html; charset=utf-8", report.to_html()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    with_deadline(
+        keep_alive.write_timeout,
+        writer.write_all(response.as_bytes()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Checks that the server can take new players.
+///
+/// # Errors
+///
+/// * `T3p0Error::ShuttingDown` - If the server has stopped accepting players.
+/// * `T3p0Error::StateActorStopped` - If the state actor is gone.
+/// * `T3p0Error::StateActorTimeout` - If the state actor didn't answer within `READINESS_DEADLINE`.
+#[cfg(feature = "status-page")]
+async fn check_readiness(
+    tx: &MailboxSender<GameRequest>,
+    accepting: bool,
+) -> Result<(), T3p0Error> {
+    if !accepting {
+        return Err(T3p0Error::ShuttingDown);
+    }
+    let probe = async {
+        let (response_tx, mut response_rx) = mpsc::channel::<Option<GameState>>(1);
+        tx.send(
+            Lane::Low,
+            GameRequest::GetState {
+                player_id: Player::from_bytes(Uuid::nil().as_bytes()),
+                response: response_tx,
+            },
+        )
+        .await
+        .ok()?;
+        response_rx.recv().await
+    };
+    match tokio::time::timeout(READINESS_DEADLINE, probe).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(T3p0Error::StateActorStopped),
+        Err(_) => Err(T3p0Error::StateActorTimeout),
+    }
+}
+
+/// Answers every discovery query with the server's announcement.
+async fn answer_discovery_queries(socket: UdpSocket, announcement: Announcement) {
+    let datagram = announcement.to_datagram();
+    let mut buffer = [0u8; 64];
+    loop {
+        match socket.recv_from(&mut buffer).await {
+            Ok((n, source)) if &buffer[..n] == DISCOVERY_QUERY => {
+                if let Err(e) = socket.send_to(&datagram, source).await {
+                    eprintln!("Discovery answer error: {:?}", e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Discovery error: {:?}", e),
+        }
+    }
+}
+
+/// Runs a socket operation with a deadline so a peer that stops responding can't hold a task forever.
+/// Operations that aren't cancel safe, like `write_all`, leave the stream in an unknown state when
+/// they time out, so the connection must be closed after a timeout.
+async fn with_deadline<T>(
+    deadline: Duration,
+    operation: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    match tokio::time::timeout(deadline, operation).await {
+        Ok(result) => result,
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "Socket operation timed out",
+        )),
+    }
+}
+
+/// Binds the listener with `SO_REUSEPORT` (on unix) so a new server binary can bind the same
+/// address while the old one is still draining, allowing upgrades without refusing connections.
+fn bind_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(true)?;
+    socket.bind(addr)?;
+    socket.listen(1024)
+}
+
+/// Turns on OS level TCP keepalive probes for a connection.
+fn set_tcp_keepalive(socket: &TcpStream, keep_alive: &KeepAliveConfig) -> std::io::Result<()> {
+    let probes = TcpKeepalive::new().with_time(keep_alive.tcp_idle);
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    let probes = probes
+        .with_interval(keep_alive.tcp_interval)
+        .with_retries(keep_alive.tcp_retries);
+    SockRef::from(socket).set_tcp_keepalive(&probes)
+}
+
+/// Asks for a player id then redraws that player's board whenever it changes.
+/// Anything typed after the id is ignored, the observer can never change the game.
+async fn handle_observer(
+    socket: TcpStream,
+    tx: MailboxSender<GameRequest>,
+    write_timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    with_deadline(write_timeout, writer.write_all(b"Player id to observe: ")).await?;
+    let player = loop {
+        let Some(line) = with_deadline(OBSERVER_PROMPT_TIMEOUT, lines.next_line()).await? else {
+            return Ok(());
+        };
+        match Uuid::parse_str(line.trim()) {
+            Ok(id) => break Player::from_bytes(id.as_bytes()),
+            Err(_) => {
+                with_deadline(
+                    write_timeout,
+                    writer.write_all(b"Not a valid id, try again: "),
+                )
+                .await?
+            }
+        }
+    };
+
+    // What is currently on the observer's screen, `None` until the first draw.
+    let mut drawn = None;
+    let mut refresh = tokio::time::interval(OBSERVER_REFRESH_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = refresh.tick() => {}
+            line = lines.next_line() => {
+                if line?.is_none() {
+                    return Ok(());
+                }
+                continue;
+            }
+        }
+
+        let (response_tx, mut response_rx) = mpsc::channel::<Option<GameState>>(1);
+        tx.send(
+            Lane::Low,
+            GameRequest::GetState {
+                player_id: player.clone(),
+                response: response_tx,
+            },
+        )
+        .await?;
+        // A private game looks the same as no game so observers can't tell it exists.
+        let state = response_rx
+            .recv()
+            .await
+            .flatten()
+            .filter(|game_state| game_state.allows_spectators())
+            .map(|game_state| game_state.to_request());
+        if drawn == Some(state) {
+            continue;
+        }
+        drawn = Some(state);
+
+        // Clear the screen and move the cursor home before drawing.
+        let mut screen = String::from("\x1b[2J\x1b[H");
+        match &state {
+            Some(request) => screen.push_str(&render_board(request)),
+            None => screen.push_str(&format!("No game for {}\r\n", player.get_id())),
+        }
+        with_deadline(write_timeout, writer.write_all(screen.as_bytes())).await?;
+    }
+}
+
+async fn handle_connection(
+    mut socket: TcpStream,
+    context: &ConnectionContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ConnectionContext {
+        tx,
+        stats,
+        event_log,
+        keep_alive,
+        peers,
+        matchmaker,
+        ..
+    } = context;
+    let keep_alive = *keep_alive;
+    let quarantine = context.quarantine.as_deref();
+    let mut closing = context.closing.clone();
+    let mut buffer = [0u8; 4];
+    let mut player = Player::new();
+    let mut options = MatchOptions::default();
+    println!("New connection: {}", socket.peer_addr()?);
+    println!("Player: {:?}", player);
+    // Handshake
+    for i in 0..2 {
+        let n = with_deadline(keep_alive.handshake_timeout, socket.read(&mut buffer)).await?;
+        if n == 0 {
+            return Err(T3p0Error::ConnectionClosed.into());
+        }
+
+        // Client should first send hello (or ok) message
+        // The server will assign a player number to the client.
+        // The user should then send another ok message
+        // If the player instead responds with a player id, the server will assign the player number to the client.
+        match n {
+            4 => {
+                let request = Request(u32::from_be_bytes(buffer));
+                if i == 0 && request.is_ok_response() {
+                    with_deadline(
+                        keep_alive.handshake_timeout,
+                        socket.write_all(&player.get_id().to_bytes_le()),
+                    )
+                    .await?;
+                }
+                if i == 1 {
+                    options = MatchOptions::from_request(request);
+                }
+            }
+            16 => {
+                if i == 0 {
+                    return Err(T3p0Error::InvalidHandshake.into());
+                }
+                let mut uuid_buffer = [0u8; 16];
+                uuid_buffer[..4].copy_from_slice(&buffer);
+                with_deadline(
+                    keep_alive.handshake_timeout,
+                    socket.read_exact(&mut uuid_buffer[4..]),
+                )
+                .await?;
+                player = Player::from_bytes(&uuid_buffer);
+                with_deadline(
+                    keep_alive.handshake_timeout,
+                    socket.write_all(&Request::new_data_request(true).0.to_be_bytes()),
+                )
+                .await?;
+            }
+            _ => {
+                return Err(T3p0Error::InvalidHandshake.into());
+            }
+        }
+    }
+
+    // Event loop
+    // Replies go through a bounded queue drained by a writer task, so a client that stops
+    // reading can't make the server buffer without limit.
+    let peer = socket.peer_addr().ok();
+    let (mut reader, writer) = socket.into_split();
+    let me = Peer {
+        queue: Arc::new(StdMutex::new(SendQueue::new(
+            SEND_QUEUE_CAPACITY,
+            context.overflow_policy,
+        ))),
+        queued: Arc::new(Notify::new()),
+        privacy: options.privacy,
+    };
+    let sender = tokio::spawn(send_queued_frames(
+        writer,
+        me.queue.clone(),
+        me.queued.clone(),
+        keep_alive.write_timeout,
+    ));
+    let send = |class: FrameClass, frame: &[u8]| me.push(class, frame);
+    lock(peers).insert(player.clone(), me.clone());
+
+    let log = |event: LogEvent| {
+        if !event_log.record(event) {
+            stats.log_event_dropped();
+        }
+    };
+
+    let result: Result<(), Box<dyn std::error::Error + Send + Sync>> =
+        async {
+            // A player without a game waits for an opponent, unless they asked to play both
+            // sides. Resumed players go back to their game.
+            if get_state(tx, &player).await?.is_none() {
+                if options.mode == GameMode::Sandbox {
+                    start_sandbox(tx, peers, player.clone(), options.privacy).await?;
+                } else {
+                    let paired = lock(matchmaker).join(player.clone());
+                    if let Some((first, second)) = paired {
+                        start_match(tx, peers, first, second).await?;
+                    }
+                }
+            }
+
+            let mut core = ConnectionCore::new(
+                player.clone(),
+                TokenBucket::new(FRAME_BURST, FRAMES_PER_SECOND),
+                MAX_RATE_LIMITED_FRAMES,
+            );
+            loop {
+                // Reading is cancel safe, so if the connection goes quiet we can ping and keep waiting.
+                let read = tokio::select! {
+                    read = tokio::time::timeout(keep_alive.ping_interval, reader.read(&mut buffer)) => read,
+                    _ = closing.wait_for(|&closing| closing) => break,
+                };
+                let mut actions: VecDeque<Action> =
+                    match read {
+                        Ok(Ok(0)) => break,
+                        Ok(read) => {
+                            let n = read?;
+                            stats.frame_received();
+                            core.on_frame(&buffer[..n], Instant::now()).into()
+                        }
+                        Err(_) => core.on_idle().into(),
+                    };
+                while let Some(action) = actions.pop_front() {
+                    match action {
+                        Action::Send(class, request) => send(class, &request.0.to_be_bytes())?,
+                        Action::Nack(nack) => {
+                            stats.nack_sent();
+                            log(LogEvent::new(
+                                LogEventKind::NackSent(nack.code),
+                                &player,
+                                nack.state,
+                                Duration::ZERO,
+                            ));
+                            send(FrameClass::Reply, &nack.to_bytes())?;
+                        }
+                        Action::QueryState => {
+                            let game_state = get_state(tx, &player).await?;
+                            actions.extend(core.on_state(game_state));
+                        }
+                        Action::Apply(game_state) => {
+                            let frame = game_state.to_request().0.to_be_bytes();
+                            let others = game_state.get_roster().map_or(Vec::new(), |roster| {
+                                let mut others = roster.team(0).to_vec();
+                                others.extend_from_slice(roster.team(1));
+                                others.retain(|member| member != &player);
+                                others
+                            });
+                            tx.send(
+                                Lane::High,
+                                GameRequest::UpdateState {
+                                    player_id: player.clone(),
+                                    new_state: game_state,
+                                },
+                            )
+                            .await?;
+                            let peers = lock(peers);
+                            for peer in others.iter().filter_map(|member| peers.get(member)) {
+                                peer.relay(&frame);
+                            }
+                        }
+                        Action::Quarantine { raw, reason } => {
+                            quarantine_frame(quarantine, &raw, &player, peer, reason)
+                        }
+                        Action::MoveAnswered(received) => {
+                            let elapsed = received.elapsed();
+                            stats.move_answered(elapsed);
+                            // A move is always a full 4 byte frame, so `buffer` holds it.
+                            log(LogEvent::new(
+                                LogEventKind::MoveAnswered,
+                                &player,
+                                Request(u32::from_be_bytes(buffer)),
+                                elapsed,
+                            ));
+                        }
+                        Action::GameOver(result) => {
+                            println!("Game over: {:?} finished with {:?}", player, result);
+                            tx.send(
+                                Lane::High,
+                                GameRequest::EndGame {
+                                    player_id: player.clone(),
+                                },
+                            )
+                            .await?;
+                        }
+                        Action::Close(reason) => return Err(reason.into()),
+                    }
+                }
+            }
+            Ok(())
+        }
+        .await;
+
+    lock(matchmaker).leave(&player);
+    {
+        // A resumed connection for the same player may have replaced us already.
+        let mut peers = lock(peers);
+        if peers
+            .get(&player)
+            .is_some_and(|peer| Arc::ptr_eq(&peer.queue, &me.queue))
+        {
+            peers.remove(&player);
+        }
+    }
+    if let Err(e) = leave_game(tx, peers, &player).await {
+        eprintln!("Failed to leave the game: {:?}", e);
+    }
+
+    // Let the writer flush whatever is still queued, including a final NACK, before closing.
+    lock(&me.queue).close();
+    me.queued.notify_one();
+    let sent = sender.await?;
+    result.map_err(|e| e as Box<dyn std::error::Error>)?;
+    Ok(sent?)
+}
+
+/// Locks a mutex shared between connections. A connection that panicked while holding it can't
+/// have left the matchmaker or peer map half updated, so a poisoned lock is still used.
+fn lock<T>(mutex: &StdMutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Asks the state actor for a player's game.
+async fn get_state(
+    tx: &MailboxSender<GameRequest>,
+    player: &Player,
+) -> Result<Option<GameState>, Box<dyn std::error::Error + Send + Sync>> {
+    let (response_tx, mut response_rx) = mpsc::channel::<Option<GameState>>(1);
+    tx.send(
+        Lane::High,
+        GameRequest::GetState {
+            player_id: player.clone(),
+            response: response_tx,
+        },
+    )
+    .await?;
+    Ok(response_rx.recv().await.flatten())
+}
+
+/// Removes the player's game once nobody in it is connected, so abandoned games aren't kept.
+async fn leave_game(
+    tx: &MailboxSender<GameRequest>,
+    peers: &StdMutex<HashMap<Player, Peer>>,
+    player: &Player,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(game_state) = get_state(tx, player).await? else {
+        return Ok(());
+    };
+    let connected = {
+        let peers = lock(peers);
+        members(player, &game_state)
+            .iter()
+            .any(|member| peers.contains_key(member))
+    };
+    if !connected {
+        tx.send(
+            Lane::High,
+            GameRequest::EndGame {
+                player_id: player.clone(),
+            },
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Stores a new game for two matched players and sends both of them the empty board.
+/// `first` moves first and plays X, and the board sent to `second` has the second side bit set.
+/// The game gets the stricter of the privacy settings the two connected players asked for.
+async fn start_match(
+    tx: &MailboxSender<GameRequest>,
+    peers: &StdMutex<HashMap<Player, Peer>>,
+    first: Player,
+    second: Player,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Match: {:?} plays {:?}", first, second);
+    let privacy = {
+        let peers = lock(peers);
+        [&first, &second]
+            .into_iter()
+            .filter_map(|player| peers.get(player))
+            .map(|peer| peer.privacy)
+            .max()
+            .unwrap_or_default()
+    };
+    let game_state = new_match(first.clone(), second.clone()).with_privacy(privacy);
+    let start = game_state.to_request();
+    tx.send(
+        Lane::High,
+        GameRequest::UpdateState {
+            player_id: first.clone(),
+            new_state: game_state,
+        },
+    )
+    .await?;
+    let peers = lock(peers);
+    for (player, is_second_side) in [(first, false), (second, true)] {
+        if let Some(peer) = peers.get(&player) {
+            peer.relay(&start.set_second_side(is_second_side).0.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Stores a sandbox game where `player` plays both sides and sends them the empty board.
+async fn start_sandbox(
+    tx: &MailboxSender<GameRequest>,
+    peers: &StdMutex<HashMap<Player, Peer>>,
+    player: Player,
+    privacy: Privacy,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Sandbox: {:?} plays both sides", player);
+    let game_state = new_match(player.clone(), player.clone())
+        .with_mode(GameMode::Sandbox)
+        .with_privacy(privacy);
+    let frame = game_state.to_request().0.to_be_bytes();
+    tx.send(
+        Lane::High,
+        GameRequest::UpdateState {
+            player_id: player.clone(),
+            new_state: game_state,
+        },
+    )
+    .await?;
+    if let Some(peer) = lock(peers).get(&player) {
+        peer.relay(&frame);
+    }
+    Ok(())
+}
+
+/// Writes queued frames to the client until the queue is closed and empty.
+/// If a write fails or times out the queue is closed so the connection handler stops queueing.
+async fn send_queued_frames(
+    mut writer: OwnedWriteHalf,
+    queue: Arc<StdMutex<SendQueue>>,
+    queued: Arc<Notify>,
+    write_timeout: Duration,
+) -> std::io::Result<()> {
+    loop {
+        let (frame, closed) = {
+            let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+            (queue.pop(), queue.is_closed())
+        };
+        let Some(frame) = frame else {
+            if closed {
+                return Ok(());
+            }
+            queued.notified().await;
+            continue;
+        };
+        if let Err(e) = with_deadline(write_timeout, writer.write_all(&frame)).await {
+            queue.lock().unwrap_or_else(|e| e.into_inner()).close();
+            return Err(e);
+        }
+    }
+}
+
+/// Writes a rejected frame to the quarantine log if one is configured.
+/// Failing to write the log is reported but never fails the connection.
+fn quarantine_frame(
+    quarantine: Option<&Quarantine>,
+    raw: &[u8],
+    player: &Player,
+    peer: Option<SocketAddr>,
+    reason: T3p0Error,
+) {
+    if let Some(quarantine) = quarantine {
+        let record = QuarantineRecord::new(raw, player, peer, &reason.to_string());
+        if let Err(e) = quarantine.record(&record) {
+            eprintln!("Failed to quarantine frame: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::handshake;
+    use std::io::{Read, Write};
+
+    fn local_config() -> ServerConfig {
+        let any_port = SocketAddr::from(([127, 0, 0, 1], 0));
+        ServerConfig {
+            addr: any_port,
+            observer_addr: Some(any_port),
+            #[cfg(feature = "status-page")]
+            status_addr: any_port,
+            ..ServerConfig::default()
+        }
+    }
+
+    fn read_frame(stream: &mut std::net::TcpStream) -> Request {
+        let mut bytes = [0u8; 4];
+        stream.read_exact(&mut bytes).unwrap();
+        Request(u32::from_be_bytes(bytes))
+    }
+
+    #[tokio::test]
+    async fn embedded_server_relays_a_move() {
+        let server = Server::bind(local_config()).await.unwrap();
+        let addr = server.local_addr();
+        let play = tokio::task::spawn_blocking(move || {
+            let ok = Request::new_data_request(true);
+            let mut first = std::net::TcpStream::connect(addr).unwrap();
+            handshake(&mut first, None, MatchOptions::default()).unwrap();
+            // The answer to an Ok means the first player is already waiting for an opponent.
+            first.write_all(&ok.0.to_be_bytes()).unwrap();
+            assert_eq!(read_frame(&mut first), ok);
+
+            let mut second = std::net::TcpStream::connect(addr).unwrap();
+            handshake(&mut second, None, MatchOptions::default()).unwrap();
+            let empty_board = Request::new_data_request(false);
+            assert_eq!(read_frame(&mut first), empty_board);
+            assert_eq!(read_frame(&mut second), empty_board.set_second_side(true));
+
+            let center = empty_board
+                .increment_turn_and_message()
+                .unwrap()
+                .set_board(1 << 4);
+            first.write_all(&center.0.to_be_bytes()).unwrap();
+            assert_eq!(read_frame(&mut first), center);
+            assert_eq!(read_frame(&mut second), center);
+        });
+
+        let run = server.run();
+        tokio::pin!(run);
+        tokio::select! {
+            result = &mut run => panic!("the server stopped early: {:?}", result),
+            played = play => played.unwrap(),
+        }
+        server.shutdown();
+        run.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sandbox_connection_plays_both_sides() {
+        let server = Server::bind(local_config()).await.unwrap();
+        let addr = server.local_addr();
+        let play = tokio::task::spawn_blocking(move || {
+            let sandbox = MatchOptions {
+                mode: GameMode::Sandbox,
+                ..MatchOptions::default()
+            };
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            handshake(&mut stream, None, sandbox).unwrap();
+            let empty_board = Request::new_data_request(false);
+            assert_eq!(read_frame(&mut stream), empty_board);
+
+            let x = empty_board
+                .increment_turn_and_message()
+                .unwrap()
+                .set_board(1 << 4);
+            stream.write_all(&x.0.to_be_bytes()).unwrap();
+            assert_eq!(read_frame(&mut stream), x);
+            let o = x
+                .increment_turn_and_message()
+                .unwrap()
+                .set_board(1 << 4 | 1)
+                .set_o_marks(1);
+            stream.write_all(&o.0.to_be_bytes()).unwrap();
+            assert_eq!(read_frame(&mut stream), o);
+        });
+
+        let run = server.run();
+        tokio::pin!(run);
+        tokio::select! {
+            result = &mut run => panic!("the server stopped early: {:?}", result),
+            played = play => played.unwrap(),
+        }
+        server.shutdown();
+        run.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn games_are_removed_when_they_end_or_are_abandoned() {
+        let (tx, rx) = mailbox::<GameRequest>(4);
+        let stats = Arc::new(Stats::new());
+        tokio::spawn(run_state_actor(rx, stats.clone()));
+        let peers = StdMutex::new(HashMap::new());
+        let first = Player::from_bytes(&[1; 16]);
+        let second = Player::from_bytes(&[2; 16]);
+
+        start_match(&tx, &peers, first.clone(), second.clone())
+            .await
+            .unwrap();
+        assert!(get_state(&tx, &second).await.unwrap().is_some());
+        // One game, however many players it's stored under.
+        assert_eq!(stats.snapshot().games_active, 1);
+        tx.send(
+            Lane::High,
+            GameRequest::EndGame {
+                player_id: first.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(get_state(&tx, &first).await.unwrap().is_none());
+        assert!(get_state(&tx, &second).await.unwrap().is_none());
+        assert_eq!(stats.snapshot().games_active, 0);
+
+        start_match(&tx, &peers, first.clone(), second.clone())
+            .await
+            .unwrap();
+        // The game stays while anyone in it is still connected.
+        lock(&peers).insert(second.clone(), peer(Privacy::Public));
+        leave_game(&tx, &peers, &first).await.unwrap();
+        assert!(get_state(&tx, &first).await.unwrap().is_some());
+        lock(&peers).clear();
+        leave_game(&tx, &peers, &second).await.unwrap();
+        assert!(get_state(&tx, &first).await.unwrap().is_none());
+        assert_eq!(stats.snapshot().games_active, 0);
+    }
+
+    fn peer(privacy: Privacy) -> Peer {
+        Peer {
+            queue: Arc::new(StdMutex::new(SendQueue::new(1, OverflowPolicy::default()))),
+            queued: Arc::new(Notify::new()),
+            privacy,
+        }
+    }
+
+    #[tokio::test]
+    async fn matched_game_gets_the_stricter_privacy() {
+        let (tx, rx) = mailbox::<GameRequest>(4);
+        tokio::spawn(run_state_actor(rx, Arc::new(Stats::new())));
+        let peers = StdMutex::new(HashMap::new());
+        let first = Player::from_bytes(&[1; 16]);
+        let second = Player::from_bytes(&[2; 16]);
+        lock(&peers).insert(first.clone(), peer(Privacy::Private));
+        lock(&peers).insert(second.clone(), peer(Privacy::Unlisted));
+
+        start_match(&tx, &peers, first.clone(), second)
+            .await
+            .unwrap();
+        let game = get_state(&tx, &first).await.unwrap().unwrap();
+        assert_eq!(game.get_privacy(), Privacy::Private);
+    }
+
+    #[tokio::test]
+    async fn drain_timeout_closes_idle_connections() {
+        let server = Server::bind(ServerConfig {
+            drain_timeout: Duration::from_millis(100),
+            ..local_config()
+        })
+        .await
+        .unwrap();
+        let addr = server.local_addr();
+        let (connected_tx, connected) = tokio::sync::oneshot::channel();
+        let idle = tokio::task::spawn_blocking(move || {
+            let ok = Request::new_data_request(true);
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            handshake(&mut stream, None, MatchOptions::default()).unwrap();
+            stream.write_all(&ok.0.to_be_bytes()).unwrap();
+            assert_eq!(read_frame(&mut stream), ok);
+            connected_tx.send(()).unwrap();
+            // Waiting for an opponent who never comes, until the server closes the connection.
+            let mut rest = Vec::new();
+            stream.read_to_end(&mut rest).unwrap();
+        });
+
+        let run = server.run();
+        tokio::pin!(run);
+        tokio::select! {
+            result = &mut run => panic!("the server stopped early: {:?}", result),
+            _ = connected => {}
+        }
+        server.shutdown();
+        tokio::time::timeout(Duration::from_secs(5), run)
+            .await
+            .expect("the server never finished draining")
+            .unwrap();
+        idle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn observer_port_is_opt_in() {
+        let closed = Server::bind(ServerConfig {
+            observer_addr: None,
+            ..local_config()
+        })
+        .await
+        .unwrap();
+        assert_eq!(closed.observer_addr(), None);
+        let open = Server::bind(local_config()).await.unwrap();
+        assert!(open.observer_addr().is_some_and(|addr| addr.port() != 0));
+    }
+
+    #[tokio::test]
+    async fn runs_only_once() {
+        let server = Server::bind(local_config()).await.unwrap();
+        server.shutdown();
+        server.run().await.unwrap();
+        assert!(server.run().await.is_err());
+    }
+}
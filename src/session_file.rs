@@ -0,0 +1,243 @@
+// A small file a client keeps so it comes back as the same player after a restart.
+// The file is plain text, one `key=value` per line, and unknown keys are ignored so newer clients
+// can add fields. It's written to `<path>.tmp` and renamed over the old file, so a crash part way
+// through a save leaves the previous session in place. A file that can't be parsed is moved aside
+// to `<path>.corrupt` and treated as no session rather than stopping the client from starting.
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    game_state::Privacy,
+    match_options::MatchOptions,
+    player::{Player, PlayerTrait},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClientSession {
+    /// The player the server assigned, sent back in the handshake to resume as the same player.
+    pub player: Option<Player>,
+    /// The last server connected to, as it was given to `connect`.
+    pub last_server: Option<String>,
+    /// Who may watch this player's games, sent in the handshake's `MatchOptions`.
+    pub privacy: Option<Privacy>,
+}
+
+pub trait ClientSessionTrait {
+    fn load(path: &Path) -> io::Result<Self>
+    where
+        Self: Sized;
+    fn save(&self, path: &Path) -> io::Result<()>;
+    fn to_text(&self) -> String;
+    fn from_text(text: &str) -> Result<Self, &'static str>
+    where
+        Self: Sized;
+    fn match_options(&self) -> MatchOptions;
+}
+
+/// Appends `suffix` to the file name, e.g. `session` becomes `session.tmp`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+impl ClientSessionTrait for ClientSession {
+    /// Reads the session file. A missing file is an empty session, and so is a corrupt one
+    /// after it has been moved to `<path>.corrupt`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where the session is kept.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If the file exists but can't be read, or a corrupt file can't be moved aside.
+    fn load(path: &Path) -> io::Result<Self> {
+        let parsed = match fs::read_to_string(path) {
+            Ok(text) => ClientSession::from_text(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ClientSession::default()),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => Err("Session file is not UTF-8."),
+            Err(e) => return Err(e),
+        };
+        match parsed {
+            Ok(session) => Ok(session),
+            Err(_) => {
+                fs::rename(path, with_suffix(path, ".corrupt"))?;
+                Ok(ClientSession::default())
+            }
+        }
+    }
+
+    /// Replaces the session file. The new contents are synced to disk before the rename so
+    /// the file is never seen half written.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where the session is kept.
+    ///
+    /// # Errors
+    ///
+    /// * `io::Error` - If the file can't be written, or `InvalidInput` if the server name has a line break.
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if self
+            .last_server
+            .as_ref()
+            .is_some_and(|server| server.contains(['\n', '\r']))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The server name can't contain a line break.",
+            ));
+        }
+        let temporary = with_suffix(path, ".tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(self.to_text().as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temporary, path)
+    }
+
+    /// Formats the session as the contents of the file. Fields that aren't set are left out.
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(player) = &self.player {
+            text.push_str(&format!("player={}\n", player.get_id()));
+        }
+        if let Some(server) = &self.last_server {
+            text.push_str(&format!("server={}\n", server));
+        }
+        if let Some(privacy) = self.privacy {
+            text.push_str(&format!("privacy={}\n", privacy.name()));
+        }
+        text
+    }
+
+    /// Parses the contents of a session file.
+    ///
+    /// # Errors
+    ///
+    /// * `&'static str` - If a line isn't `key=value`, the player id isn't a UUID or the privacy
+    ///   isn't known.
+    fn from_text(text: &str) -> Result<Self, &'static str> {
+        let mut session = ClientSession::default();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let Some((key, value)) = line.split_once('=') else {
+                return Err("Session line is not key=value.");
+            };
+            match key {
+                "player" => {
+                    let id = Uuid::parse_str(value).map_err(|_| "Session player is not a UUID.")?;
+                    session.player = Some(Player::from_bytes(id.as_bytes()));
+                }
+                "server" => session.last_server = Some(value.to_string()),
+                "privacy" => session.privacy = Some(Privacy::from_name(value)?),
+                _ => {}
+            }
+        }
+        Ok(session)
+    }
+
+    /// The options to send in the handshake, so a new match keeps the stored privacy.
+    fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            privacy: self.privacy.unwrap_or_default(),
+            ..MatchOptions::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> ClientSession {
+        ClientSession {
+            player: Some(Player::from_bytes(&[7; 16])),
+            last_server: Some("game.example:8000".to_string()),
+            privacy: Some(Privacy::Unlisted),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("t3p0-session-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(with_suffix(&path, ".corrupt"));
+        path
+    }
+
+    #[test]
+    fn text_round_trip() {
+        assert_eq!(
+            ClientSession::from_text(&session().to_text()),
+            Ok(session())
+        );
+        assert_eq!(ClientSession::from_text(""), Ok(ClientSession::default()));
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let text = format!("{}token=abc\n", session().to_text());
+        assert_eq!(ClientSession::from_text(&text), Ok(session()));
+    }
+
+    #[test]
+    fn save_then_load() {
+        let path = temp_path("round-trip");
+        session().save(&path).unwrap();
+        assert_eq!(ClientSession::load(&path).unwrap(), session());
+        assert!(!with_suffix(&path, ".tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = temp_path("missing");
+        assert_eq!(
+            ClientSession::load(&path).unwrap(),
+            ClientSession::default()
+        );
+    }
+
+    #[test]
+    fn corrupt_file_is_moved_aside() {
+        let path = temp_path("corrupt");
+        fs::write(&path, "player=not-a-uuid\n").unwrap();
+        assert_eq!(
+            ClientSession::load(&path).unwrap(),
+            ClientSession::default()
+        );
+        assert!(!path.exists());
+        let corrupt = with_suffix(&path, ".corrupt");
+        assert_eq!(fs::read_to_string(&corrupt).unwrap(), "player=not-a-uuid\n");
+        fs::remove_file(corrupt).unwrap();
+    }
+
+    #[test]
+    fn privacy_is_sent_in_match_options() {
+        assert_eq!(session().match_options().privacy, Privacy::Unlisted);
+        assert_eq!(
+            ClientSession::default().match_options(),
+            MatchOptions::default()
+        );
+        assert!(ClientSession::from_text("privacy=secret\n").is_err());
+    }
+
+    #[test]
+    fn server_with_line_break_is_rejected() {
+        let path = temp_path("line-break");
+        let session = ClientSession {
+            last_server: Some("a\nplayer=x".to_string()),
+            ..session()
+        };
+        assert_eq!(
+            session.save(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
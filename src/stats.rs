@@ -0,0 +1,336 @@
+// Server statistics are kept in atomics instead of behind the game state mutex.
+// Recording a value never waits on game traffic and reading a snapshot never blocks a move.
+// The recent results list has its own mutex, only taken when a game ends or the list is read.
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{
+    game_state::{GameResult, GameState, GameStateTrait, Privacy},
+    histogram::{Histogram, HistogramSnapshot, HistogramTrait},
+    player::PlayerTrait,
+    roster::RosterTrait,
+};
+
+/// How many finished games `Stats` remembers for the status page.
+pub const RECENT_RESULTS_KEPT: usize = 10;
+/// Hex digits of a player id shown next to a public result.
+const SHORT_ID_LENGTH: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    connections_opened: AtomicU64,
+    connections_active: AtomicU64,
+    games_active: AtomicU64,
+    frames_received: AtomicU64,
+    nacks_sent: AtomicU64,
+    slow_commands: AtomicU64,
+    log_events_dropped: AtomicU64,
+    move_latency: Histogram,
+    recent_results: Mutex<VecDeque<RecentResult>>,
+}
+
+/// A finished game as it may be shown to anyone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentResult {
+    /// How the game ended, never `GameResult::InProgress`.
+    pub result: GameResult,
+    /// The start of the first player id on each side. `None` unless the game was public.
+    pub players: Option<[String; 2]>,
+}
+
+/// A point in time copy of the counters in `Stats`.
+/// Each counter is read on its own so the values may be a few events apart from each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatsSnapshot {
+    pub connections_opened: u64,
+    pub connections_active: u64,
+    /// Games stored by the state actor, each counted once however many players are in it.
+    pub games_active: u64,
+    pub frames_received: u64,
+    pub nacks_sent: u64,
+    /// State actor commands that took longer than the watchdog threshold.
+    pub slow_commands: u64,
+    /// Move path log events dropped because the logger fell behind.
+    pub log_events_dropped: u64,
+    /// Time from receiving a move frame to finishing the response to it.
+    pub move_latency: HistogramSnapshot,
+}
+
+pub trait StatsTrait {
+    fn new() -> Self;
+    fn connection_opened(&self);
+    fn connection_closed(&self);
+    fn game_started(&self);
+    fn game_ended(&self);
+    fn frame_received(&self);
+    fn nack_sent(&self);
+    fn slow_command(&self);
+    fn log_event_dropped(&self);
+    fn move_answered(&self, elapsed: Duration);
+    fn game_finished(&self, game: &GameState);
+    fn recent_results(&self) -> Vec<RecentResult>;
+    fn snapshot(&self) -> StatsSnapshot;
+}
+
+impl StatsTrait for Stats {
+    fn new() -> Self {
+        Stats::default()
+    }
+
+    /// Records a new connection. It counts towards both the total and the active connections.
+    fn connection_opened(&self) {
+        self.connections_opened.fetch_add(1, Ordering::Relaxed);
+        self.connections_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a connection has gone away. The active count never goes below zero.
+    fn connection_closed(&self) {
+        let _ =
+            self.connections_active
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |active| {
+                    active.checked_sub(1)
+                });
+    }
+
+    fn game_started(&self) {
+        self.games_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a game was removed. The active count never goes below zero.
+    fn game_ended(&self) {
+        let _ = self
+            .games_active
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |active| {
+                active.checked_sub(1)
+            });
+    }
+
+    fn frame_received(&self) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn nack_sent(&self) {
+        self.nacks_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn slow_command(&self) {
+        self.slow_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn log_event_dropped(&self) {
+        self.log_events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records how long it took to answer a move frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - The time from receiving the frame to finishing the response.
+    fn move_answered(&self, elapsed: Duration) {
+        self.move_latency.record(elapsed);
+    }
+
+    /// Remembers how a game ended. Games removed before they were won or drawn are skipped, and
+    /// the players are only kept for public games. The oldest result is dropped once
+    /// `RECENT_RESULTS_KEPT` are stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The last state of the game.
+    fn game_finished(&self, game: &GameState) {
+        let result = game.check_winner();
+        if result == GameResult::InProgress {
+            return;
+        }
+        let players = match (game.get_privacy(), game.get_roster()) {
+            (Privacy::Public, Some(roster)) => Some([0, 1].map(|side| {
+                let id = roster.team(side)[0].get_id().simple().to_string();
+                id[..SHORT_ID_LENGTH].to_string()
+            })),
+            _ => None,
+        };
+        let mut recent = self
+            .recent_results
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if recent.len() == RECENT_RESULTS_KEPT {
+            recent.pop_back();
+        }
+        recent.push_front(RecentResult { result, players });
+    }
+
+    /// Copies the remembered results.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<RecentResult>` - Up to `RECENT_RESULTS_KEPT` results, newest first.
+    fn recent_results(&self) -> Vec<RecentResult> {
+        self.recent_results
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Copies the current value of every counter.
+    ///
+    /// # Returns
+    ///
+    /// * `StatsSnapshot` - The counters at the time they were read.
+    fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            connections_opened: self.connections_opened.load(Ordering::Relaxed),
+            connections_active: self.connections_active.load(Ordering::Relaxed),
+            games_active: self.games_active.load(Ordering::Relaxed),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            nacks_sent: self.nacks_sent.load(Ordering::Relaxed),
+            slow_commands: self.slow_commands.load(Ordering::Relaxed),
+            log_events_dropped: self.log_events_dropped.load(Ordering::Relaxed),
+            move_latency: self.move_latency.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        matchmaking::new_match,
+        player::Player,
+        request::{DataRequest, Request},
+        roster::Roster,
+    };
+    use std::{sync::Arc, thread};
+
+    /// A public game X won on the top row, between players filled with `1` and `2` bytes.
+    fn won_game() -> GameState {
+        let request = Request::new_data_request(false)
+            .set_board(0b000_011_111)
+            .set_o_marks(0b000_011_000)
+            .set_turn(5)
+            .set_message_number(5)
+            .set_p2_turn(true);
+        let first = Player::from_bytes(&[1; 16]);
+        let second = Player::from_bytes(&[2; 16]);
+        GameState::from_request(request, first.clone())
+            .unwrap()
+            .with_roster(Roster::pair(first, second))
+    }
+
+    #[test]
+    fn new_is_empty() {
+        let stats = Stats::new();
+        assert_eq!(stats.snapshot(), StatsSnapshot::default());
+    }
+
+    #[test]
+    fn connection_counts() {
+        let stats = Stats::new();
+        stats.connection_opened();
+        stats.connection_opened();
+        stats.connection_closed();
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.connections_opened, 2);
+        assert_eq!(snapshot.connections_active, 1);
+    }
+
+    #[test]
+    fn game_counts() {
+        let stats = Stats::new();
+        stats.game_started();
+        stats.game_started();
+        stats.game_ended();
+        stats.game_ended();
+        stats.game_ended();
+        assert_eq!(stats.snapshot().games_active, 0);
+        stats.game_started();
+        assert_eq!(stats.snapshot().games_active, 1);
+    }
+
+    #[test]
+    fn close_without_open_stays_at_zero() {
+        let stats = Stats::new();
+        stats.connection_closed();
+        assert_eq!(stats.snapshot().connections_active, 0);
+    }
+
+    #[test]
+    fn concurrent_updates() {
+        let stats = Arc::new(Stats::new());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let stats = stats.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        stats.frame_received();
+                        stats.nack_sent();
+                        stats.slow_command();
+                        stats.log_event_dropped();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.frames_received, 8000);
+        assert_eq!(snapshot.nacks_sent, 8000);
+        assert_eq!(snapshot.slow_commands, 8000);
+        assert_eq!(snapshot.log_events_dropped, 8000);
+    }
+
+    #[test]
+    fn move_latency() {
+        let stats = Stats::new();
+        stats.move_answered(Duration::from_micros(10));
+        stats.move_answered(Duration::from_micros(20));
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.move_latency.count, 2);
+        assert_eq!(snapshot.move_latency.max_us, 20);
+    }
+
+    #[test]
+    fn recent_results_hide_players_unless_public() {
+        let stats = Stats::new();
+        stats.game_finished(&won_game());
+        stats.game_finished(&won_game().with_privacy(Privacy::Unlisted));
+        assert_eq!(
+            stats.recent_results(),
+            vec![
+                RecentResult {
+                    result: GameResult::XWins,
+                    players: None,
+                },
+                RecentResult {
+                    result: GameResult::XWins,
+                    players: Some(["01010101".to_string(), "02020202".to_string()]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn recent_results_skip_unfinished_games_and_keep_the_newest() {
+        let stats = Stats::new();
+        stats.game_finished(&new_match(
+            Player::from_bytes(&[1; 16]),
+            Player::from_bytes(&[2; 16]),
+        ));
+        assert!(stats.recent_results().is_empty());
+        for _ in 0..RECENT_RESULTS_KEPT {
+            stats.game_finished(&won_game().with_privacy(Privacy::Private));
+        }
+        stats.game_finished(&won_game());
+        let recent = stats.recent_results();
+        assert_eq!(recent.len(), RECENT_RESULTS_KEPT);
+        assert!(recent[0].players.is_some());
+    }
+}
@@ -0,0 +1,228 @@
+// The public status page.
+// A small read-only summary of the server built from the stats counters, served as HTML for
+// people and JSON for scripts. The only player ids on it are short prefixes next to the results
+// of public games.
+use std::time::Duration;
+
+use crate::{
+    game_state::GameResult,
+    stats::{RecentResult, StatsSnapshot},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusReport {
+    pub version: &'static str,
+    pub uptime: Duration,
+    pub stats: StatsSnapshot,
+    /// Players in the matchmaking queue.
+    pub players_waiting: usize,
+    /// Newest first, see `StatsTrait::recent_results`.
+    pub recent_results: Vec<RecentResult>,
+}
+
+pub trait StatusReportTrait {
+    fn new(
+        uptime: Duration,
+        stats: StatsSnapshot,
+        players_waiting: usize,
+        recent_results: Vec<RecentResult>,
+    ) -> Self;
+    fn to_json(&self) -> String;
+    fn to_html(&self) -> String;
+}
+
+/// Formats a duration as days, hours, minutes and seconds, e.g. `1d 2h 3m 4s`.
+fn format_uptime(uptime: Duration) -> String {
+    let seconds = uptime.as_secs();
+    format!(
+        "{}d {}h {}m {}s",
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+/// The name of a finished game's result as used in the JSON report.
+fn result_name(result: GameResult) -> &'static str {
+    match result {
+        GameResult::XWins => "x_wins",
+        GameResult::OWins => "o_wins",
+        GameResult::Draw => "draw",
+        GameResult::InProgress => "in_progress",
+    }
+}
+
+/// Describes a finished game for the HTML page, e.g. `X wins, 01010101 vs 02020202`.
+fn describe_result(recent: &RecentResult) -> String {
+    let result = match recent.result {
+        GameResult::XWins => "X wins",
+        GameResult::OWins => "O wins",
+        GameResult::Draw => "Draw",
+        GameResult::InProgress => "In progress",
+    };
+    match &recent.players {
+        Some([x, o]) => format!("{}, {} vs {}", result, x, o),
+        None => format!("{}, players hidden", result),
+    }
+}
+
+impl StatusReportTrait for StatusReport {
+    /// Creates a report for this build of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `uptime` - How long the server has been running.
+    /// * `stats` - The counters to report.
+    /// * `players_waiting` - The length of the matchmaking queue.
+    /// * `recent_results` - The finished games to list, newest first.
+    fn new(
+        uptime: Duration,
+        stats: StatsSnapshot,
+        players_waiting: usize,
+        recent_results: Vec<RecentResult>,
+    ) -> Self {
+        StatusReport {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime,
+            stats,
+            players_waiting,
+            recent_results,
+        }
+    }
+
+    /// Formats the report as a JSON object. The field names are stable for scripts.
+    /// `recent_results` is a list of `{"result":..,"players":..}` objects, where `players` is
+    /// `null` for games that weren't public.
+    fn to_json(&self) -> String {
+        let recent_results: Vec<String> = self
+            .recent_results
+            .iter()
+            .map(|recent| {
+                let players = match &recent.players {
+                    Some([x, o]) => format!("[\"{}\",\"{}\"]", x, o),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "{{\"result\":\"{}\",\"players\":{}}}",
+                    result_name(recent.result),
+                    players
+                )
+            })
+            .collect();
+        format!(
+            "{{\"version\":\"{}\",\"uptime_seconds\":{},\"active_games\":{},\
+             \"connections_active\":{},\"connections_opened\":{},\"frames_received\":{},\
+             \"log_events_dropped\":{},\"move_latency_p50_us\":{},\"move_latency_p99_us\":{},\
+             \"players_waiting\":{},\"recent_results\":[{}]}}",
+            self.version,
+            self.uptime.as_secs(),
+            self.stats.games_active,
+            self.stats.connections_active,
+            self.stats.connections_opened,
+            self.stats.frames_received,
+            self.stats.log_events_dropped,
+            self.stats.move_latency.p50_us,
+            self.stats.move_latency.p99_us,
+            self.players_waiting,
+            recent_results.join(",")
+        )
+    }
+
+    /// Formats the report as a minimal HTML page.
+    fn to_html(&self) -> String {
+        let rows = [
+            ("Version", self.version.to_string()),
+            ("Uptime", format_uptime(self.uptime)),
+            ("Active games", self.stats.games_active.to_string()),
+            ("Players waiting", self.players_waiting.to_string()),
+            (
+                "Connected players",
+                self.stats.connections_active.to_string(),
+            ),
+            (
+                "Connections served",
+                self.stats.connections_opened.to_string(),
+            ),
+            (
+                "Move latency (p50 / p99)",
+                format!(
+                    "{} / {} &micro;s",
+                    self.stats.move_latency.p50_us, self.stats.move_latency.p99_us
+                ),
+            ),
+        ];
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html><head><title>T3P0 Status</title></head><body>\n\
+             <h1>T3P0 Status</h1>\n<table>\n",
+        );
+        for (name, value) in rows {
+            html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, value));
+        }
+        html.push_str("</table>\n<h2>Recent results</h2>\n<ul>\n");
+        for recent in &self.recent_results {
+            html.push_str(&format!("<li>{}</li>\n", describe_result(recent)));
+        }
+        html.push_str("</ul>\n</body></html>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> StatusReport {
+        let stats = StatsSnapshot {
+            connections_opened: 10,
+            connections_active: 2,
+            games_active: 1,
+            frames_received: 40,
+            ..StatsSnapshot::default()
+        };
+        let recent_results = vec![
+            RecentResult {
+                result: GameResult::XWins,
+                players: Some(["01010101".to_string(), "02020202".to_string()]),
+            },
+            RecentResult {
+                result: GameResult::Draw,
+                players: None,
+            },
+        ];
+        StatusReport::new(Duration::from_secs(90_061), stats, 3, recent_results)
+    }
+
+    #[test]
+    fn format_uptime_parts() {
+        assert_eq!(format_uptime(Duration::from_secs(0)), "0d 0h 0m 0s");
+        assert_eq!(format_uptime(Duration::from_secs(90_061)), "1d 1h 1m 1s");
+    }
+
+    #[test]
+    fn to_json() {
+        assert_eq!(
+            report().to_json(),
+            format!(
+                "{{\"version\":\"{}\",\"uptime_seconds\":90061,\"active_games\":1,\
+                 \"connections_active\":2,\"connections_opened\":10,\"frames_received\":40,\
+                 \"log_events_dropped\":0,\"move_latency_p50_us\":0,\"move_latency_p99_us\":0,\
+                 \"players_waiting\":3,\"recent_results\":[\
+                 {{\"result\":\"x_wins\",\"players\":[\"01010101\",\"02020202\"]}},\
+                 {{\"result\":\"draw\",\"players\":null}}]}}",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn to_html() {
+        let html = report().to_html();
+        assert!(html.contains("<tr><th>Uptime</th><td>1d 1h 1m 1s</td></tr>"));
+        assert!(html.contains("<tr><th>Active games</th><td>1</td></tr>"));
+        assert!(html.contains("<tr><th>Connected players</th><td>2</td></tr>"));
+        assert!(html.contains("<tr><th>Players waiting</th><td>3</td></tr>"));
+        assert!(html.contains("<li>X wins, 01010101 vs 02020202</li>"));
+        assert!(html.contains("<li>Draw, players hidden</li>"));
+    }
+}
@@ -0,0 +1,524 @@
+// Generates a markdown description of the wire format from the constants the codec uses,
+// so client authors in other languages always have a spec that matches the source.
+// Run `cargo run --bin t3p0-wire-docs` to print it.
+use crate::{
+    match_options::{PRIVACY_OFFSET, PRIVACY_RANGE, SANDBOX_OFFSET},
+    nack::{
+        NackCode, BACKOFF_OFFSET, BACKOFF_RANGE, CODE_RANGE, NACK_FLAG_OFFSET, RETRYABLE_OFFSET,
+    },
+    request::{Bits, Ranges},
+};
+
+/// A range of bits inside a 32 bit frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// Offset of the least significant bit of the field.
+    pub offset: u32,
+    /// Number of bits in the field.
+    pub width: u32,
+    pub description: &'static str,
+}
+
+/// The fields of a data request, from the least significant bit up.
+pub fn data_request_fields() -> Vec<Field> {
+    let board = Ranges::Board as u32;
+    let dry_run = Bits::DryRun as u32;
+    vec![
+        Field {
+            name: "Board State",
+            offset: 0,
+            width: board,
+            description:
+                "One bit per square, square 0 is the top left and square 8 the bottom right.",
+        },
+        Field {
+            name: "O Marks",
+            offset: Bits::OMarks as u32,
+            width: board,
+            description:
+                "Set for filled squares holding the second player's mark, same layout as the board.",
+        },
+        Field {
+            name: "Dry Run",
+            offset: dry_run,
+            width: 1,
+            description: "1 asks whether the move is legal without making it.",
+        },
+        Field {
+            name: "Second Side",
+            offset: Bits::SecondSide as u32,
+            width: 1,
+            description:
+                "Set by the server in the match start frame sent to the player who places O.",
+        },
+        Field {
+            name: "Unused",
+            offset: Bits::SecondSide as u32 + 1,
+            width: Bits::MessageNumber as u32 - Bits::SecondSide as u32 - 1,
+            description: "Must be 0. Bit 20 is the NACK flag of a NACK header.",
+        },
+        Field {
+            name: "Message Number",
+            offset: Bits::MessageNumber as u32,
+            width: Ranges::MessageNumber as u32,
+            description: "Number of messages exchanged in the match, at most 26.",
+        },
+        Field {
+            name: "Is P2 Turn",
+            offset: Bits::P2Turn as u32,
+            width: 1,
+            description: "Set when it is player 2's turn.",
+        },
+        Field {
+            name: "Turn Number",
+            offset: Bits::TurnOffset as u32,
+            width: Ranges::Turn as u32,
+            description:
+                "Turn within the current game, at most 8. Must equal the message number mod 9.",
+        },
+        Field {
+            name: "Message Type",
+            offset: Bits::MessageType as u32,
+            width: 1,
+            description: "0 for a data request. An Ok response is this bit alone.",
+        },
+    ]
+}
+
+/// The fields of the first 32 bits of a NACK, from the least significant bit up.
+pub fn nack_header_fields() -> Vec<Field> {
+    vec![
+        Field {
+            name: "Error Code",
+            offset: 0,
+            width: CODE_RANGE,
+            description: "See the error code table.",
+        },
+        Field {
+            name: "Backoff Hint",
+            offset: BACKOFF_OFFSET,
+            width: BACKOFF_RANGE,
+            description: "How long to wait before retrying, in units of 10ms. 0 for no hint.",
+        },
+        Field {
+            name: "Unused",
+            offset: BACKOFF_OFFSET + BACKOFF_RANGE,
+            width: RETRYABLE_OFFSET - BACKOFF_OFFSET - BACKOFF_RANGE,
+            description: "Must be 0.",
+        },
+        Field {
+            name: "Retryable",
+            offset: RETRYABLE_OFFSET,
+            width: 1,
+            description: "1 if sending the same frame again later can succeed.",
+        },
+        Field {
+            name: "NACK Flag",
+            offset: NACK_FLAG_OFFSET,
+            width: 1,
+            description: "Always 1.",
+        },
+        Field {
+            name: "Unused",
+            offset: NACK_FLAG_OFFSET + 1,
+            width: Bits::MessageType as u32 - NACK_FLAG_OFFSET - 1,
+            description: "Must be 0.",
+        },
+        Field {
+            name: "Message Type",
+            offset: Bits::MessageType as u32,
+            width: 1,
+            description: "Always 1.",
+        },
+    ]
+}
+
+/// The fields of the match options sent at the end of the handshake, from the least significant bit up.
+pub fn match_options_fields() -> Vec<Field> {
+    vec![
+        Field {
+            name: "Sandbox",
+            offset: SANDBOX_OFFSET,
+            width: 1,
+            description:
+                "1 plays both sides on this connection instead of waiting for an opponent.",
+        },
+        Field {
+            name: "Privacy",
+            offset: PRIVACY_OFFSET,
+            width: PRIVACY_RANGE,
+            description:
+                "0 public, 1 unlisted, 2 private. A matched game gets the stricter of the two.",
+        },
+        Field {
+            name: "Unused",
+            offset: PRIVACY_OFFSET + PRIVACY_RANGE,
+            width: Bits::MessageType as u32 - PRIVACY_OFFSET - PRIVACY_RANGE,
+            description: "Must be 0.",
+        },
+        Field {
+            name: "Message Type",
+            offset: Bits::MessageType as u32,
+            width: 1,
+            description: "Always 1.",
+        },
+    ]
+}
+
+/// Formats the bits a field covers, e.g. `0-8` or `31`.
+fn bit_range(field: &Field) -> String {
+    if field.width == 1 {
+        return field.offset.to_string();
+    }
+    format!("{}-{}", field.offset, field.offset + field.width - 1)
+}
+
+fn field_table(fields: &[Field]) -> String {
+    let mut table =
+        String::from("| Bits | Field | Description |\n|------|-------|-------------|\n");
+    for field in fields {
+        table.push_str(&format!(
+            "| {} | {} | {} |\n",
+            bit_range(field),
+            field.name,
+            field.description
+        ));
+    }
+    table
+}
+
+/// Builds the full wire format description.
+///
+/// # Returns
+///
+/// * `String` - A markdown document.
+pub fn markdown() -> String {
+    let mut doc = String::from("# T3P0 Wire Format\n\n");
+    doc.push_str(
+        "Every frame is made of big endian unsigned 32 bit integers. \
+         Bits are numbered from the least significant bit.\n\n",
+    );
+
+    doc.push_str("## Data Request (4 bytes)\n\n");
+    doc.push_str(&field_table(&data_request_fields()));
+
+    doc.push_str("\n## Ok Response (4 bytes)\n\n");
+    doc.push_str(&format!(
+        "Only bit {} is set: `0x{:08x}`.\n",
+        Bits::MessageType as u32,
+        1u32 << Bits::MessageType as u32
+    ));
+
+    doc.push_str("\n## Match Options (4 bytes)\n\n");
+    doc.push_str(
+        "Sent by the client in place of the second Ok of the handshake. \
+         A plain Ok asks for the defaults.\n\n",
+    );
+    doc.push_str(&field_table(&match_options_fields()));
+
+    doc.push_str("\n## NACK (8 bytes)\n\n");
+    doc.push_str(
+        "A header followed by a data request holding the server's authoritative state.\n\n",
+    );
+    doc.push_str(&field_table(&nack_header_fields()));
+
+    doc.push_str(
+        "\n### Error Codes\n\nClients should decide whether to retry from the header, not the code. \
+         The table shows what this server sends.\n\n\
+         | Code | Name | Retryable | Backoff | Description |\n\
+         |------|------|-----------|---------|-------------|\n",
+    );
+    for code in NackCode::ALL {
+        doc.push_str(&format!(
+            "| {} | {:?} | {} | {}ms | {} |\n",
+            code as u8,
+            code,
+            if code.is_retryable() { "yes" } else { "no" },
+            code.backoff_hint().as_millis(),
+            code.description()
+        ));
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nack::{Nack, NackTrait},
+        request::{DataRequest, Request},
+    };
+
+    /// Checks that the fields are in order, don't overlap and cover all 32 bits.
+    fn assert_covers_frame(fields: &[Field]) {
+        let mut next = 0;
+        for field in fields {
+            assert_eq!(
+                field.offset, next,
+                "{} doesn't start where the last field ended",
+                field.name
+            );
+            assert!(field.width > 0);
+            next = field.offset + field.width;
+        }
+        assert_eq!(next, 32);
+    }
+
+    #[test]
+    fn data_request_covers_frame() {
+        assert_covers_frame(&data_request_fields());
+    }
+
+    #[test]
+    fn match_options_cover_frame() {
+        assert_covers_frame(&match_options_fields());
+    }
+
+    #[test]
+    fn nack_header_covers_frame() {
+        assert_covers_frame(&nack_header_fields());
+    }
+
+    fn mask(field: &Field) -> u32 {
+        (((1u64 << field.width) - 1) << field.offset) as u32
+    }
+
+    #[test]
+    fn data_request_fields_match_codec() {
+        for field in data_request_fields() {
+            let written = match field.name {
+                "Board State" => Request(0).set_board(u16::MAX),
+                "O Marks" => Request(0).set_o_marks(u16::MAX),
+                "Dry Run" => Request(0).set_dry_run(true),
+                "Second Side" => Request(0).set_second_side(true),
+                "Message Number" => Request(0).set_message_number(u8::MAX),
+                "Is P2 Turn" => Request(0).set_p2_turn(true),
+                "Turn Number" => Request(0).set_turn(u8::MAX),
+                "Message Type" => Request::new_data_request(true),
+                "Unused" => continue,
+                name => panic!("{} has no setter to check against", name),
+            };
+            assert_eq!(written.0, mask(&field), "{}", field.name);
+        }
+    }
+
+    #[test]
+    fn nack_flag_is_unused_in_data_requests() {
+        let flag = 1 << NACK_FLAG_OFFSET;
+        let field = data_request_fields()
+            .into_iter()
+            .find(|field| mask(field) & flag != 0)
+            .unwrap();
+        assert_eq!(field.name, "Unused");
+    }
+
+    /// The diagram at the top of request.rs numbers rows from 1 at the most significant bit.
+    /// Each named row starts a field that runs until the next named row.
+    fn request_diagram_fields() -> Vec<(String, u32, u32)> {
+        let mut fields: Vec<(String, u32, u32)> = Vec::new();
+        for line in include_str!("request.rs").lines() {
+            let cells: Vec<&str> = line.split('|').map(str::trim).collect();
+            let [prefix, row, name, ..] = cells[..] else {
+                continue;
+            };
+            let Ok(row) = row.parse::<u32>() else {
+                continue;
+            };
+            if prefix != "///" {
+                continue;
+            }
+            let bit = 32 - row;
+            match fields.last_mut() {
+                Some((_, offset, width)) if name.is_empty() => {
+                    *offset = bit;
+                    *width += 1;
+                }
+                _ => fields.push((name.to_string(), bit, 1)),
+            }
+        }
+        fields.reverse();
+        fields
+    }
+
+    #[test]
+    fn request_diagram_matches_fields() {
+        let expected: Vec<(String, u32, u32)> = data_request_fields()
+            .iter()
+            .map(|field| (field.name.to_string(), field.offset, field.width))
+            .collect();
+        assert_eq!(request_diagram_fields(), expected);
+    }
+
+    #[test]
+    fn markdown_lists_fields_and_codes() {
+        let doc = markdown();
+        assert!(doc.contains("| 0-8 | Board State |"));
+        assert!(doc.contains("| 9-17 | O Marks |"));
+        assert!(doc.contains("| 21-25 | Message Number |"));
+        assert!(doc.contains("| 26 | Is P2 Turn |"));
+        assert!(doc.contains("| 27-30 | Turn Number |"));
+        assert!(doc.contains("`0x80000000`"));
+        assert!(doc.contains("| 20 | NACK Flag |"));
+        assert!(doc.contains("| 0 | Sandbox |"));
+        assert!(doc.contains("| 1-2 | Privacy |"));
+        for code in NackCode::ALL {
+            assert!(doc.contains(&format!("| {} | {:?} |", code as u8, code)));
+        }
+    }
+
+    #[test]
+    fn nack_codes_round_trip() {
+        for code in NackCode::ALL {
+            assert_eq!(NackCode::try_from(code as u8), Ok(code));
+        }
+    }
+
+    /// Frames from `fixtures/frames.hex` keyed by name.
+    fn golden_frames() -> Vec<(String, Vec<u8>)> {
+        include_str!("../fixtures/frames.hex")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut parts = line.split_whitespace();
+                let name = parts.next().unwrap().to_string();
+                let hex: String = parts.collect();
+                let bytes = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                    .collect();
+                (name, bytes)
+            })
+            .collect()
+    }
+
+    fn golden_frame(name: &str) -> Vec<u8> {
+        golden_frames()
+            .into_iter()
+            .find(|(frame, _)| frame == name)
+            .unwrap_or_else(|| panic!("{} is missing from fixtures/frames.hex", name))
+            .1
+    }
+
+    /// The requests the golden fixtures were generated from, and whether they pass validation.
+    fn golden_requests() -> Vec<(&'static str, Request, bool)> {
+        let first_move_center = Request::new_data_request(false)
+            .set_board(1 << 4)
+            .set_turn(1)
+            .set_message_number(1)
+            .set_p2_turn(true);
+        let full_board = Request::new_data_request(false)
+            .set_board(0b111111111)
+            .set_turn(8)
+            .set_message_number(8);
+        let x_and_o_marks = Request::new_data_request(false)
+            .set_board(0b000_010_001)
+            .set_o_marks(0b000_010_000)
+            .set_turn(2)
+            .set_message_number(2);
+        let o_mark_on_empty_square = Request::new_data_request(false).set_o_marks(1);
+        let dry_run_first_move = first_move_center.set_dry_run(true);
+        let max_message_number = Request::new_data_request(false)
+            .set_turn(8)
+            .set_message_number(26);
+        vec![
+            ("ok_response", Request::new_data_request(true), true),
+            ("empty_board", Request::new_data_request(false), true),
+            ("first_move_center", first_move_center, true),
+            ("full_board", full_board, true),
+            ("x_and_o_marks", x_and_o_marks, true),
+            ("o_mark_on_empty_square", o_mark_on_empty_square, false),
+            ("dry_run_first_move", dry_run_first_move, true),
+            (
+                "match_start_second_side",
+                Request::new_data_request(false).set_second_side(true),
+                true,
+            ),
+            ("max_message_number", max_message_number, true),
+            (
+                "swapped_empty_board",
+                Request::new_data_request(false).swap_player(),
+                false,
+            ),
+        ]
+    }
+
+    fn golden_nacks() -> Vec<(&'static str, Nack)> {
+        let requests = golden_requests();
+        let state = |name: &str| requests.iter().find(|(n, _, _)| *n == name).unwrap().1;
+        vec![
+            (
+                "nack_invalid_frame",
+                Nack::new(NackCode::InvalidFrame, state("empty_board")),
+            ),
+            (
+                "nack_invalid_request",
+                Nack::new(NackCode::InvalidRequest, state("first_move_center")),
+            ),
+            (
+                "nack_illegal_move",
+                Nack::new(NackCode::IllegalMove, state("full_board")),
+            ),
+            (
+                "nack_rate_limited",
+                Nack::new(NackCode::RateLimited, state("max_message_number")),
+            ),
+        ]
+    }
+
+    #[test]
+    fn golden_requests_encode() {
+        for (name, request, _) in golden_requests() {
+            assert_eq!(
+                request.0.to_be_bytes().to_vec(),
+                golden_frame(name),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn golden_requests_decode() {
+        for (name, request, valid) in golden_requests() {
+            let bytes: [u8; 4] = golden_frame(name).try_into().unwrap();
+            let decoded = Request(u32::from_be_bytes(bytes));
+            assert_eq!(decoded, request, "{}", name);
+            if !decoded.is_ok_response() {
+                assert_eq!(decoded.validate_request().is_ok(), valid, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn golden_nacks_encode() {
+        for (name, nack) in golden_nacks() {
+            assert_eq!(nack.to_bytes().to_vec(), golden_frame(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn golden_nacks_decode() {
+        for (name, nack) in golden_nacks() {
+            let bytes: [u8; 8] = golden_frame(name).try_into().unwrap();
+            assert_eq!(Nack::from_bytes(&bytes), Ok(nack), "{}", name);
+        }
+    }
+
+    #[test]
+    fn golden_fixtures_are_all_covered() {
+        // Every fixture must be checked by one of the tests above, and every NACK code must have a fixture.
+        let covered: Vec<&str> = golden_requests()
+            .iter()
+            .map(|(name, _, _)| *name)
+            .chain(golden_nacks().iter().map(|(name, _)| *name))
+            .collect();
+        for (name, _) in golden_frames() {
+            assert!(covered.contains(&name.as_str()), "{} is not checked", name);
+        }
+        for code in NackCode::ALL {
+            assert!(golden_nacks().iter().any(|(_, nack)| nack.code == code));
+        }
+    }
+}